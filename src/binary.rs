@@ -0,0 +1,160 @@
+//! `--binary`: a fixed-layout record for status bars reading the output of `--watch` from a pipe
+//!
+//! Every integer is little-endian:
+//!
+//! | offset | size | field                                               |
+//! |--------|------|-----------------------------------------------------|
+//! | 0      | 40   | `u32` counts, in this order: ahead, behind, staged, |
+//! |        |      | conflicts, changed, untracked, stashed, deleted,    |
+//! |        |      | renamed, copied                                     |
+//! | 40     | 1    | `u8` clean, 1 or 0                                  |
+//! | 41     | 4    | `u32` length N of the branch                        |
+//! | 45     | N    | branch, UTF-8                                       |
+//!
+//! Counts past `u32::MAX` are saturated. A record knows its own length, so `--watch` writes them
+//! back to back without a terminator. The other fields aren't part of the layout.
+
+use crate::Status;
+
+/// Bytes before the branch
+const HEADER_LEN: usize = COUNTS * 4 + 1 + 4;
+
+/// Number of `u32` counts at the start of a record
+const COUNTS: usize = 10;
+
+pub fn encode(status: &Status) -> Vec<u8> {
+    let counts = [
+        status.ahead,
+        status.behind,
+        status.staged,
+        status.conflicts,
+        status.changed,
+        status.untracked,
+        status.stashed,
+        status.deleted,
+        status.renamed,
+        status.copied,
+    ];
+
+    let mut record = Vec::with_capacity(HEADER_LEN + status.branch.len());
+    for count in counts {
+        record.extend(u32::try_from(count).unwrap_or(u32::MAX).to_le_bytes());
+    }
+    record.push(status.clean as u8);
+    record.extend(
+        u32::try_from(status.branch.len())
+            .unwrap_or(u32::MAX)
+            .to_le_bytes(),
+    );
+    record.extend(status.branch.as_bytes());
+    record
+}
+
+/// Reads back the first record of `bytes`, with the number of bytes it took, `None` when the
+/// record is incomplete or its branch isn't UTF-8
+pub fn decode(bytes: &[u8]) -> Option<(Status, usize)> {
+    let header = bytes.get(..HEADER_LEN)?;
+    let u32_at = |offset: usize| {
+        let mut word = [0; 4];
+        word.copy_from_slice(&header[offset..offset + 4]);
+        u32::from_le_bytes(word) as usize
+    };
+    let word = |index: usize| u32_at(index * 4);
+
+    let branch_len = u32_at(COUNTS * 4 + 1);
+    let branch = bytes.get(HEADER_LEN..HEADER_LEN + branch_len)?;
+
+    let status = Status {
+        ahead: word(0),
+        behind: word(1),
+        staged: word(2),
+        conflicts: word(3),
+        changed: word(4),
+        untracked: word(5),
+        stashed: word(6),
+        deleted: word(7),
+        renamed: word(8),
+        copied: word(9),
+        clean: header[COUNTS * 4] == 1,
+        branch: String::from_utf8(branch.to_vec()).ok()?,
+        ..Default::default()
+    };
+    Some((status, HEADER_LEN + branch_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let status = Status {
+            branch: "feature/ünïcödé-✓".to_string(),
+            ahead: 1,
+            behind: 2,
+            staged: 3,
+            conflicts: 4,
+            changed: 5,
+            untracked: 6,
+            stashed: 7,
+            deleted: 8,
+            renamed: 9,
+            copied: 10,
+            clean: false,
+            ..Default::default()
+        };
+        let mut record = encode(&status);
+        assert_eq!(record.len(), HEADER_LEN + status.branch.len());
+        // a record is read on its own even with the next one right behind it
+        record.extend(encode(&Status::default()));
+
+        let (decoded, len) = decode(&record).unwrap();
+        assert_eq!(len, HEADER_LEN + status.branch.len());
+        assert_eq!(decoded.branch, status.branch);
+        assert_eq!(
+            [
+                decoded.ahead,
+                decoded.behind,
+                decoded.staged,
+                decoded.conflicts,
+                decoded.changed
+            ],
+            [1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            [
+                decoded.untracked,
+                decoded.stashed,
+                decoded.deleted,
+                decoded.renamed,
+                decoded.copied
+            ],
+            [6, 7, 8, 9, 10]
+        );
+        assert!(!decoded.clean);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn saturated_count() {
+        let status = Status {
+            untracked: u32::MAX as usize + 1,
+            clean: true,
+            ..Default::default()
+        };
+        let (decoded, _) = decode(&encode(&status)).unwrap();
+        assert_eq!(decoded.untracked, u32::MAX as usize);
+        assert!(decoded.clean);
+        assert!(decoded.branch.is_empty());
+    }
+
+    #[test]
+    fn incomplete_record() {
+        let record = encode(&Status {
+            branch: "main".to_string(),
+            ..Default::default()
+        });
+        assert!(decode(&record[..record.len() - 1]).is_none());
+        assert!(decode(&record[..HEADER_LEN - 1]).is_none());
+    }
+}
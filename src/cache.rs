@@ -0,0 +1,69 @@
+//! On-disk cache of the rendered output, keyed by git dir and arguments
+//!
+//! An entry is reused for as long as `$GIT_DIR/index` and `$GIT_DIR/HEAD` keep the modification
+//! times they had when it was written. Edits to the working tree that don't touch the index
+//! aren't noticed, which is the trade-off for skipping `git status` entirely.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+pub struct Cache {
+    path: PathBuf,
+    git_dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(cache_dir: &str, git_dir: &str, args: &[String]) -> Option<Cache> {
+        let git_dir = fs::canonicalize(git_dir).ok()?;
+
+        let mut hasher = DefaultHasher::new();
+        git_dir.hash(&mut hasher);
+        args.hash(&mut hasher);
+
+        Some(Cache {
+            path: Path::new(cache_dir).join(format!("{:016x}", hasher.finish())),
+            git_dir,
+        })
+    }
+
+    /// Returns the cached output, if the entry is still valid
+    pub fn load(&self) -> Option<String> {
+        let content = fs::read_to_string(&self.path).ok()?;
+        let (stamp, output) = content.split_once('\n')?;
+
+        (stamp == self.stamp()).then(|| output.to_string())
+    }
+
+    /// Stores the output; failing to write the cache never fails the run.
+    ///
+    /// Must be called after `git status` ran, as it may have refreshed the index.
+    pub fn store(&self, output: &str) {
+        if let Some(cache_dir) = self.path.parent() {
+            let _ = fs::create_dir_all(cache_dir);
+        }
+        let _ = fs::write(&self.path, format!("{}\n{}", self.stamp(), output));
+    }
+
+    fn stamp(&self) -> String {
+        format!(
+            "{} {}",
+            modified(&self.git_dir.join("index")),
+            modified(&self.git_dir.join("HEAD"))
+        )
+    }
+}
+
+/// Modification time in nanoseconds, or 0 when the file doesn't exist (e.g. no index yet)
+fn modified(path: &Path) -> u128 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_nanos())
+        .unwrap_or(0)
+}
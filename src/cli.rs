@@ -0,0 +1,1048 @@
+//! Command line parsing
+
+use crate::config::Config;
+use std::{collections::HashMap, io::IsTerminal, time::Duration};
+
+/// Exit code for invalid arguments (`EX_USAGE`)
+pub const USAGE_ERROR: i32 = 64;
+
+/// Exit code when git fails for any reason other than not being in a repository
+pub const GIT_ERROR: i32 = 2;
+
+/// Exit code when the output can't be written (`EX_IOERR`)
+pub const OUTPUT_ERROR: i32 = 74;
+
+/// `--quiet` exit code of a dirty repository
+pub const DIRTY: i32 = 1;
+
+/// `--fail-on-conflict` exit code when there are unresolved conflicts
+pub const CONFLICTED: i32 = 3;
+
+/// `--quiet` exit code outside a repository
+pub const NOT_A_REPO: i32 = 4;
+
+pub const USAGE: &str = "usage: gitstatus [OPTIONS]\nTry 'gitstatus --help' for more information.";
+
+pub const HELP: &str = concat!(
+    "gitstatus ",
+    env!("CARGO_PKG_VERSION"),
+    "\n",
+    env!("CARGO_PKG_DESCRIPTION"),
+    "
+
+Prints the status of the current git repository as a single space separated line,
+or nothing at all outside a repository. Bare repositories only get their branch.
+A field without a value (no operation in progress, no upstream) is printed as `-`.
+
+Usage: gitstatus [OPTIONS]
+
+Output:
+      --json               Print a JSON object instead (`{}` outside a repository)
+      --format <TEMPLATE>  Render a template like \"{branch} +{staged}\", `{{`/`}}` escape braces.
+                           Only the fields it uses are computed, opt-in ones included
+  -z, --null               Separate the fields with NUL instead of spaces, an empty field
+                           staying empty
+      --short              Print a compact segment like \"main ↑2↓1 *3 +2\", zero counts left out
+      --fields <LIST>      Only compute and print these comma separated fields, in order
+      --binary             Write a fixed-layout little-endian record instead of text, for
+                           programs reading --watch (the layout is documented in `binary.rs`)
+      --color <WHEN>       Color the pending counts: `auto` (on a terminal), `always` or `never`
+      --max-branch-len <N> Cut the branch (or tag/hash) to N characters, ending with `…`, N
+                           being at least 1
+      --cap <N>            Print the counts above N as `N+` (e.g. `99+`), booleans aside
+      --cap-json           Apply --cap to --json too, a capped count becoming a string
+      --branch-abbrev      Shorten the branch namespaces: `feature/foo/bar` becomes `f/f/bar`
+      --encode <HOW>       Escape the branch: `url` (percent-encoded, `/` included), `shell`
+                           (single quoted) or `none` (default)
+      --prefix <TEXT>      Print TEXT before the output, unless there is none
+      --suffix <TEXT>      Print TEXT after the output, unless there is none
+      --output <PATH>      Write to PATH (truncated first, a named pipe works too) instead of
+                           stdout
+      --config <FILE>      Read glyphs, colors and a default --format template from a TOML
+                           file (overridden by the environment and the output flags)
+      --only-if-dirty      Print nothing when there are no changes, nothing ahead or behind and
+                           no stash
+  -q, --quiet              Print nothing, only exit with 0 when clean and 1 when dirty (4
+                           outside a repository, a timeout or a locked index count as dirty)
+      --fail-on-conflict   Exit with 3 when there are unresolved conflicts, after the output
+      --dirty-includes <LIST>
+                           What besides changes makes --only-if-dirty print and --quiet exit
+                           with 1, out of `ahead`, `behind` and `stash` (default: all of them,
+                           empty for none)
+      --warn-<COUNT> <N>   Set warning when the count is above N, for ahead, behind, staged,
+                           changed, untracked, conflicts, stashed and deleted (warning)
+      --watch              Keep running, printing a NUL terminated line whenever it changes
+                           (with --json: one object per line, the same as a single --json run)
+
+Repository:
+  -C, --directory <PATH>   Run against PATH instead of the current directory
+      --repos <PATHS>      Run against each of the comma separated PATHS, printing one
+                           `PATH output` line each, the output left empty when a path can't be
+                           read. With -z the path and the fields are NUL separated, and each
+                           line still ends with a newline
+      --porcelain-version <VERSION>
+                           Parse `git status --porcelain=v1` (`1`, the default), `v2` (`2`,
+                           git 2.11 and later) or v2 when git supports it (`auto`)
+      --porcelain-v2       Same as `--porcelain-version=2`
+      --stdin              Parse `git status --porcelain --branch` (or `-sb`) output from stdin
+                           instead of running git (no stash count, no lookups that need git)
+      --backend <BACKEND>  `subprocess` (default) or `libgit2` (requires the libgit2 feature)
+      --untracked-mode <MODE>
+                           Untracked files to look for: `normal` (a new directory counts once),
+                           `all` (every file in it, slower on big trees) or `no`. Defaults to
+                           git's `status.showUntrackedFiles`
+      --git-args <ARGS>    Extra whitespace separated git status arguments, e.g.
+                           \"--ignore-submodules=dirty -- src\". Those changing the output
+                           format are refused
+      --no-untracked       Same as `--untracked-mode=no`: faster on big trees, but untracked is
+                           always 0 and doesn't affect clean
+      --timeout-ms <MS>    Stop git status after MS milliseconds and print the branch only
+      --retries <N>        Run git status again up to N times (default 1) while another git
+                           process holds the index lock, then print the branch only
+      --cache-dir <DIR>    Reuse the output until the index or HEAD change
+      --no-cache           Refresh the cached output instead of reading it
+
+Extra fields:
+      --show-ignored       Count ignored files (runs git status --ignored)
+      --untracked-dirs     Count top-level untracked directories
+      --submodules         Count submodules with new commits or conflicts
+      --compare <REF>      Count commits ahead/behind REF
+      --since-base [<REF>] Count commits since HEAD forked from REF (since_base), origin/HEAD
+                           when REF is left out or the field is listed alone in --fields or
+                           --format
+      --with-dirty         Flag changes in the working tree (inverse of clean)
+      --net-divergence     Ahead minus behind as a single signed count (divergence)
+      --with-staged-clean  Flag staged changes with nothing else pending (ready to commit)
+      --ext-count          Count the distinct extensions of the changed tracked files
+      --histogram          Count the entries by XY state (histogram), e.g. `.M=3,??=5,MM=1`
+                           with `.` for an unchanged side, or an object like {\" M\":3} in --json
+      --split-deleted      Count staged (`D `) and unstaged (` D`) deletions separately
+      --partially-staged   Count files with both staged and unstaged changes (`MM`, `AM`, `RM`)
+      --added              Count new files added as is (`A `), they stay part of staged too
+      --unstaged-total     Count the files with changes not staged yet, untracked ones aside
+      --conflict-breakdown Count the conflicts by kind: both_deleted (DD), added_by_us (AU),
+                           deleted_by_them (UD), added_by_them (UA), deleted_by_us (DU),
+                           both_added (AA) and both_modified (UU)
+      --diffstat           Count inserted/deleted lines, staged and unstaged
+      --with-hash          Short hash of HEAD (empty before the first commit)
+      --with-tag           Tag HEAD points at, on a branch too (empty when there is none)
+      --unpushed-total     Count commits on any local branch that no remote has
+      --with-sparse        Flag an enabled sparse checkout
+      --with-shallow       Flag a shallow clone, whose ahead/behind may be off (warned about
+                           with --verbose)
+      --with-tracked-count Count the files in the index (tracked)
+      --with-remote-count  Count the configured remotes (remote_count), 0 for a local-only repo
+      --with-hidden-changes
+                           Count the files marked assume-unchanged and skip-worktree, whose
+                           changes are never reported (assume_unchanged, skip_worktree)
+      --with-reflog-count <DAYS>
+                           Count the HEAD reflog entries of the last DAYS, 0 for all of them
+                           (reflog_count), listing the field alone uses 0
+      --with-commit-age    How long ago HEAD was committed, e.g. \"3 hours ago\" (commit_age).
+                           It contains spaces, use -z to split the fields reliably
+      --include-stash-details
+                           Subject of the latest stash, e.g. \"On main: wip\" (stash_subject),
+                           empty without stashes. It contains spaces as well
+      --stash-current-branch
+                           Only count the stashes created on the current branch (stashed)
+      --with-operation     The operation in progress with its step and the conflicts left, e.g.
+                           \"rebase 3/7 conflicts=2\" (operation_state)
+      --with-root          Path of the work tree (root) and its directory name (root_name)
+      --no-branch          Leave the branch out, every other field moves one position to the
+                           left, and a detached HEAD isn't named
+
+      --selftest           Check the setup: print the git version, the repository found and
+                           every field with its value, and how long reading it took
+      --debug              Print how long each git invocation took to stderr
+  -v, --verbose            Print what git wrote to stderr when it failed (outside a repository
+                           excepted)
+  -h, --help               Print this help
+  -V, --version            Print the version
+      --field-names        Print the names of the fields the other options would output, in
+                           order and with the same separator
+
+Environment:
+  GITSTATUS_<FLAG>         Default for a flag, e.g. GITSTATUS_NO_UNTRACKED=1,
+                           GITSTATUS_MAX_BRANCH_LEN=20 or GITSTATUS_FORMAT (used when no output
+                           flag is given). The command line and then the environment win over
+                           --config, and an output flag (--json, --short, --format, --fields,
+                           --binary) on the command line drops the environment's, as --git-args
+                           drops GITSTATUS_GIT_ARGS. A switch set in the environment can't be
+                           turned off by a flag, set it to 0 instead (GITSTATUS_QUIET=0 gitstatus)
+  GITSTATUS_CACHE          Default for --cache-dir
+  GITSTATUS_GIT_BIN        git binary to run (default: git)
+  GITSTATUS_GLYPH_<FIELD>  --short glyph of AHEAD (↑), BEHIND (↓), STAGED (*), CHANGED (~),
+                           DELETED (-), UNTRACKED (+), CONFLICTS (!) or STASHED ($)
+  GITSTATUS_COLOR_<FIELD>  SGR color of a --color field (CONFLICTS, STAGED, CHANGED, DELETED,
+                           UNTRACKED), e.g. \"1;32\"
+
+Exit status:
+  0   success, including outside a repository or without git installed
+  1   --quiet: the repository is dirty
+  2   git failed
+  3   --fail-on-conflict: there are unresolved conflicts
+  4   --quiet: not a repository
+  64  invalid arguments
+  74  the output couldn't be written
+"
+);
+
+/// What `--since-base` counts from without a ref
+const DEFAULT_BASE: &str = "origin/HEAD";
+
+/// Flags picking the kind of output: any of them on the command line drops the others'
+/// defaults from the environment, so `GITSTATUS_JSON=1 gitstatus --short` is short
+const OUTPUT_FLAGS: [&str; 5] = ["--json", "--short", "--format", "--fields", "--binary"];
+
+/// Flags that default from the environment, `--max-branch-len` from `GITSTATUS_MAX_BRANCH_LEN`,
+/// and whether they take a value. One-off modes (`--help`, `--watch`, `--stdin`...) and
+/// `--directory` and `--output`, which only make sense for a single run, have none.
+const ENV_FLAGS: [(&str, bool); 68] = [
+    ("--json", false),
+    ("--null", false),
+    ("--short", false),
+    ("--porcelain-version", true),
+    ("--backend", true),
+    ("--timeout-ms", true),
+    ("--retries", true),
+    ("--cache-dir", true),
+    ("--no-cache", false),
+    ("--show-ignored", false),
+    ("--untracked-dirs", false),
+    ("--submodules", false),
+    ("--compare", true),
+    ("--since-base", true),
+    ("--with-reflog-count", true),
+    ("--with-dirty", false),
+    ("--net-divergence", false),
+    ("--with-staged-clean", false),
+    ("--ext-count", false),
+    ("--histogram", false),
+    ("--split-deleted", false),
+    ("--partially-staged", false),
+    ("--added", false),
+    ("--unstaged-total", false),
+    ("--conflict-breakdown", false),
+    ("--diffstat", false),
+    ("--with-hash", false),
+    ("--with-tag", false),
+    ("--unpushed-total", false),
+    ("--with-sparse", false),
+    ("--with-shallow", false),
+    ("--with-tracked-count", false),
+    ("--with-remote-count", false),
+    ("--with-commit-age", false),
+    ("--with-hidden-changes", false),
+    ("--with-root", false),
+    ("--no-branch", false),
+    ("--include-stash-details", false),
+    ("--stash-current-branch", false),
+    ("--with-operation", false),
+    ("--debug", false),
+    ("--verbose", false),
+    ("--untracked-mode", true),
+    ("--no-untracked", false),
+    ("--git-args", true),
+    ("--color", true),
+    ("--max-branch-len", true),
+    ("--cap", true),
+    ("--cap-json", false),
+    ("--branch-abbrev", false),
+    ("--encode", true),
+    ("--prefix", true),
+    ("--suffix", true),
+    ("--fields", true),
+    ("--config", true),
+    ("--only-if-dirty", false),
+    ("--quiet", false),
+    ("--fail-on-conflict", false),
+    ("--binary", false),
+    ("--dirty-includes", true),
+    ("--warn-ahead", true),
+    ("--warn-behind", true),
+    ("--warn-staged", true),
+    ("--warn-changed", true),
+    ("--warn-untracked", true),
+    ("--warn-conflicts", true),
+    ("--warn-stashed", true),
+    ("--warn-deleted", true),
+];
+
+/// Counts a `--warn-<count>` threshold can be set for
+const WARN_FIELDS: [&str; 8] = [
+    "ahead",
+    "behind",
+    "staged",
+    "changed",
+    "untracked",
+    "conflicts",
+    "stashed",
+    "deleted",
+];
+
+/// Fields of `--conflict-breakdown`, one per unmerged XY pair
+const CONFLICT_FIELDS: [&str; 7] = [
+    "both_deleted",
+    "added_by_us",
+    "deleted_by_them",
+    "added_by_them",
+    "deleted_by_us",
+    "both_added",
+    "both_modified",
+];
+
+/// Which `git status --porcelain` format is read
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum PorcelainVersion {
+    /// understood by every git version
+    #[default]
+    V1,
+    /// git 2.11 and later
+    V2,
+    /// v2 when git supports it, or whichever the `--stdin` input is in
+    Auto,
+}
+
+#[derive(Clone, Default, PartialEq)]
+pub enum Backend {
+    /// spawn `git` for everything
+    #[default]
+    Subprocess,
+    /// read the repository in-process, requires the `libgit2` feature
+    Libgit2,
+}
+
+/// How the branch is escaped on output (`--encode`)
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Encoding {
+    /// verbatim
+    #[default]
+    None,
+    /// percent-encoded, everything but the URL unreserved characters
+    Url,
+    /// single quoted for a POSIX shell
+    Shell,
+}
+
+/// Which untracked files git status looks for (`--untracked-files`)
+#[derive(Clone, Copy, PartialEq)]
+pub enum UntrackedMode {
+    /// untracked directories as a single `dir/` entry, git's own default
+    Normal,
+    /// every file inside untracked directories too
+    All,
+    /// no untracked files at all
+    No,
+}
+
+#[derive(Clone, Default)]
+pub struct Options {
+    pub help: bool,
+    pub version: bool,
+    pub field_names: bool,
+    pub selftest: bool,
+    pub json: bool,
+    pub format: Option<String>,
+    pub null: bool,
+    pub short: bool,
+    pub porcelain: PorcelainVersion,
+    pub directory: Option<String>,
+    pub repos: Option<Vec<String>>,
+    pub cache_dir: Option<String>,
+    pub no_cache: bool,
+    pub timeout: Option<Duration>,
+    pub retries: u32,
+    pub show_ignored: bool,
+    pub untracked_dirs: bool,
+    pub submodules: bool,
+    pub compare: Option<String>,
+    pub since_base: Option<String>,
+    pub reflog_days: Option<u64>,
+    pub with_dirty: bool,
+    pub net_divergence: bool,
+    pub with_staged_clean: bool,
+    pub ext_count: bool,
+    pub histogram: bool,
+    pub split_deleted: bool,
+    pub partially_staged: bool,
+    pub added: bool,
+    pub unstaged_total: bool,
+    pub conflict_breakdown: bool,
+    pub diffstat: bool,
+    pub with_hash: bool,
+    pub with_tag: bool,
+    pub unpushed_total: bool,
+    pub with_sparse: bool,
+    pub with_shallow: bool,
+    pub with_tracked_count: bool,
+    pub with_remote_count: bool,
+    pub with_commit_age: bool,
+    pub with_hidden_changes: bool,
+    pub with_root: bool,
+    pub with_operation: bool,
+    pub no_branch: bool,
+    pub stash_details: bool,
+    pub stash_current_branch: bool,
+    pub fail_on_conflict: bool,
+    pub binary: bool,
+    pub fields: Option<Vec<String>>,
+    pub prefix: Option<String>,
+    pub output: Option<String>,
+    pub watch: bool,
+    pub debug: bool,
+    pub verbose: bool,
+    pub stdin: bool,
+    pub untracked_mode: Option<UntrackedMode>,
+    pub git_args: Vec<String>,
+    pub color: bool,
+    pub max_branch_len: Option<usize>,
+    pub cap: Option<u64>,
+    pub cap_json: bool,
+    pub branch_abbrev: bool,
+    pub encode: Encoding,
+    pub suffix: Option<String>,
+    pub backend: Backend,
+    pub config: Config,
+    pub only_if_dirty: bool,
+    pub quiet: bool,
+    pub dirty_includes: Option<Vec<String>>,
+    /// `--warn-<count>` thresholds by count, in the order given
+    pub warn: Vec<(String, usize)>,
+}
+
+impl Options {
+    /// Parses the arguments (without the program name) on top of the defaults the process
+    /// environment sets, see [`Options::from_env_and_args`]
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+        Options::from_env_and_args(std::env::vars(), args)
+    }
+
+    /// Parses the arguments (without the program name), accepting both `--flag value` and
+    /// `--flag=value` for flags that take a value.
+    ///
+    /// Each flag of `ENV_FLAGS` defaults from its `GITSTATUS_<FLAG>` variable: the value itself,
+    /// or `1`/`true`/`yes` for a switch. The arguments come after those defaults, so a flag given
+    /// on the command line wins. An output flag (`OUTPUT_FLAGS`) on the command line replaces
+    /// every output default from the environment, and `--git-args` replaces `GITSTATUS_GIT_ARGS`
+    /// instead of adding to it, while other switches add up: one set in the environment can only
+    /// be turned off there. Likewise `GITSTATUS_FORMAT` is a default
+    /// template, used when no output flag is set, that wins over the `--config` one.
+    pub fn from_env_and_args(
+        env: impl IntoIterator<Item = (String, String)>,
+        args: impl IntoIterator<Item = String>,
+    ) -> Result<Options, String> {
+        let env = env
+            .into_iter()
+            .filter(|(name, _)| name.starts_with("GITSTATUS_"))
+            .collect::<HashMap<_, _>>();
+        let args = args.into_iter().collect::<Vec<_>>();
+        let given = |flags: &[&str]| {
+            args.iter().any(|arg| {
+                let flag = arg.split_once('=').map_or(arg.as_str(), |(flag, _)| flag);
+                flags.contains(&flag)
+            })
+        };
+        let output_arg = given(&OUTPUT_FLAGS);
+        // `--git-args` add up, the environment's would stay in front of the command line's
+        let git_args_arg = given(&["--git-args"]);
+        let mut defaults = vec![];
+        for (flag, takes_value) in ENV_FLAGS {
+            if output_arg && OUTPUT_FLAGS.contains(&flag) || git_args_arg && flag == "--git-args" {
+                continue;
+            }
+            let name = format!(
+                "GITSTATUS_{}",
+                flag.trim_start_matches('-')
+                    .replace('-', "_")
+                    .to_uppercase()
+            );
+            match env.get(&name).map(String::as_str) {
+                None | Some("") => {}
+                Some(value) if takes_value => defaults.push(format!("{}={}", flag, value)),
+                Some("1" | "true" | "yes") => defaults.push(flag.to_string()),
+                Some("0" | "false" | "no") => {}
+                Some(value) => {
+                    return Err(format!("invalid {} '{}', expected 1 or 0", name, value))
+                }
+            }
+        }
+
+        let mut options = Options {
+            cache_dir: env.get("GITSTATUS_CACHE").cloned(),
+            retries: 1,
+            ..Default::default()
+        };
+        let mut args = defaults.into_iter().chain(args).peekable();
+
+        while let Some(arg) = args.next() {
+            let (flag, mut inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg, None),
+            };
+            // the ref is optional, a bare `--since-base` is followed by another flag or nothing
+            if flag == "--since-base" {
+                let base = inline.or_else(|| args.next_if(|next| !next.starts_with('-')));
+                options.since_base = Some(base.unwrap_or_else(|| DEFAULT_BASE.to_string()));
+                continue;
+            }
+            let mut value = || {
+                inline
+                    .take()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{} requires a value", flag))
+            };
+
+            match flag.as_str() {
+                "-h" | "--help" => options.help = true,
+                "-V" | "--version" => options.version = true,
+                "--field-names" => options.field_names = true,
+                "--selftest" => options.selftest = true,
+                "--json" => options.json = true,
+                "--binary" => options.binary = true,
+                "--format" => options.format = Some(value()?),
+                "-z" | "--null" => options.null = true,
+                "--short" => options.short = true,
+                "--porcelain-version" => options.porcelain = parse_porcelain(&value()?)?,
+                "--porcelain-v2" => options.porcelain = PorcelainVersion::V2,
+                "-C" | "--directory" => options.directory = Some(value()?),
+                "--repos" => options.repos = Some(parse_repos(&value()?)),
+                "--backend" => options.backend = parse_backend(&value()?)?,
+                "--timeout-ms" => options.timeout = Some(parse_ms(&value()?)?),
+                "--retries" => options.retries = parse_retries(&value()?)?,
+                "--cache-dir" => options.cache_dir = Some(value()?),
+                "--no-cache" => options.no_cache = true,
+                "--show-ignored" => options.show_ignored = true,
+                "--untracked-dirs" => options.untracked_dirs = true,
+                "--submodules" => options.submodules = true,
+                "--compare" => options.compare = Some(value()?),
+                "--with-reflog-count" => options.reflog_days = Some(parse_days(&value()?)?),
+                "--with-dirty" => options.with_dirty = true,
+                "--net-divergence" => options.net_divergence = true,
+                "--with-staged-clean" => options.with_staged_clean = true,
+                "--ext-count" => options.ext_count = true,
+                "--histogram" => options.histogram = true,
+                "--split-deleted" => options.split_deleted = true,
+                "--partially-staged" => options.partially_staged = true,
+                "--added" => options.added = true,
+                "--unstaged-total" => options.unstaged_total = true,
+                "--conflict-breakdown" => options.conflict_breakdown = true,
+                "--diffstat" => options.diffstat = true,
+                "--with-hash" => options.with_hash = true,
+                "--with-tag" => options.with_tag = true,
+                "--unpushed-total" => options.unpushed_total = true,
+                "--with-sparse" => options.with_sparse = true,
+                "--with-shallow" => options.with_shallow = true,
+                "--with-tracked-count" => options.with_tracked_count = true,
+                "--with-remote-count" => options.with_remote_count = true,
+                "--with-commit-age" => options.with_commit_age = true,
+                "--include-stash-details" => options.stash_details = true,
+                "--stash-current-branch" => options.stash_current_branch = true,
+                "--with-hidden-changes" => options.with_hidden_changes = true,
+                "--with-root" => options.with_root = true,
+                "--no-branch" => options.no_branch = true,
+                "--with-operation" => options.with_operation = true,
+                "--watch" => options.watch = true,
+                "--debug" => options.debug = true,
+                "-v" | "--verbose" => options.verbose = true,
+                "--stdin" => options.stdin = true,
+                "--untracked-mode" => {
+                    options.untracked_mode = Some(parse_untracked_mode(&value()?)?)
+                }
+                "--no-untracked" => options.untracked_mode = Some(UntrackedMode::No),
+                "--git-args" => options.git_args.extend(parse_git_args(&value()?)?),
+                "--color" => options.color = parse_color(&value()?)?,
+                "--max-branch-len" => options.max_branch_len = Some(parse_len(&value()?)?),
+                "--cap" => options.cap = Some(parse_cap(&value()?)?),
+                "--cap-json" => options.cap_json = true,
+                "--branch-abbrev" => options.branch_abbrev = true,
+                "--encode" => options.encode = parse_encoding(&value()?)?,
+                "--output" => options.output = Some(value()?),
+                "--prefix" => options.prefix = Some(value()?),
+                "--suffix" => options.suffix = Some(value()?),
+                "--fields" => options.fields = Some(parse_fields(&value()?)?),
+                "--config" => options.config = Config::load(&value()?)?,
+                "--only-if-dirty" => options.only_if_dirty = true,
+                "-q" | "--quiet" => options.quiet = true,
+                "--fail-on-conflict" => options.fail_on_conflict = true,
+                "--dirty-includes" => {
+                    options.dirty_includes = Some(parse_dirty_includes(&value()?)?)
+                }
+                flag if is_warn_flag(flag) => {
+                    let field = flag.trim_start_matches("--warn-").to_string();
+                    let threshold = parse_threshold(&value()?)?;
+                    // the command line overrides the environment
+                    options.warn.retain(|(warned, _)| *warned != field);
+                    options.warn.push((field, threshold));
+                }
+                _ => return Err(format!("unknown option '{}'", flag)),
+            }
+
+            if inline.is_some() {
+                return Err(format!("{} doesn't take a value", flag));
+            }
+        }
+
+        if options.binary
+            && (options.json
+                || options.short
+                || options.fields.is_some()
+                || options.format.is_some())
+        {
+            return Err(
+                "--binary can't be used with --json, --short, --fields or --format".to_string(),
+            );
+        }
+
+        // the environment's and the file's templates are only defaults, any output flag wins
+        if !options.json
+            && !options.short
+            && !options.binary
+            && options.fields.is_none()
+            && options.format.is_none()
+        {
+            let format = env
+                .get("GITSTATUS_FORMAT")
+                .filter(|format| !format.is_empty());
+            options.format = format.or(options.config.format.as_ref()).cloned();
+        }
+
+        // a template only needs the fields it mentions, same as listing them with `--fields`
+        if let (None, Some(template), false) = (&options.fields, &options.format, options.json) {
+            options.fields = Some(crate::template_fields(template));
+        }
+
+        if options.fields.is_some() {
+            // with an explicit list, opt-in fields are computed exactly when they're listed
+            options.show_ignored = options.wants("ignored");
+            options.untracked_dirs = options.wants("untracked_dirs");
+            options.submodules = options.wants("submodules_dirty");
+            options.with_dirty = options.wants("dirty");
+            options.net_divergence = options.wants("divergence");
+            options.with_staged_clean = options.wants("staged_clean");
+            options.ext_count = options.wants("ext_count");
+            options.histogram = options.wants("histogram");
+            options.split_deleted =
+                options.wants("deleted_staged") || options.wants("deleted_unstaged");
+            options.partially_staged = options.wants("partially_staged");
+            options.added = options.wants("added");
+            options.unstaged_total = options.wants("unstaged_total");
+            options.conflict_breakdown = CONFLICT_FIELDS.iter().any(|field| options.wants(field));
+            options.diffstat = options.wants("insertions") || options.wants("deletions");
+            options.with_hash = options.wants("hash");
+            options.with_tag = options.wants("tag");
+            options.unpushed_total = options.wants("unpushed_total");
+            options.with_sparse = options.wants("sparse");
+            options.with_shallow = options.wants("shallow");
+            options.with_tracked_count = options.wants("tracked");
+            options.with_remote_count = options.wants("remote_count");
+            options.with_commit_age = options.wants("commit_age");
+            options.stash_details = options.wants("stash_subject");
+            options.with_hidden_changes =
+                options.wants("assume_unchanged") || options.wants("skip_worktree");
+            options.with_root = options.wants("root") || options.wants("root_name");
+            options.with_operation = options.wants("operation_state");
+            options.no_branch = !options.wants("branch");
+            if !options.wants("compare_ahead") && !options.wants("compare_behind") {
+                options.compare = None;
+            }
+            options.reflog_days = match options.reflog_days {
+                _ if !options.wants("reflog_count") => None,
+                days => days.or(Some(0)),
+            };
+            options.since_base = match options.since_base.take() {
+                _ if !options.wants("since_base") => None,
+                base => base.or_else(|| Some(DEFAULT_BASE.to_string())),
+            };
+        }
+
+        if options.backend == Backend::Libgit2 && !options.git_args.is_empty() {
+            return Err("--git-args can't be used with the libgit2 backend".to_string());
+        }
+
+        if options.repos.is_some() && (options.watch || options.stdin || options.binary) {
+            return Err("--repos can't be used with --watch, --stdin or --binary".to_string());
+        }
+
+        if options.fail_on_conflict && (options.watch || options.repos.is_some()) {
+            return Err("--fail-on-conflict can't be used with --watch or --repos".to_string());
+        }
+
+        if options.stdin {
+            if options.watch {
+                return Err("--stdin can't be used with --watch".to_string());
+            }
+            // only what the captured porcelain tells can be reported, lookups that need git
+            // are left out (and the stash count stays at 0)
+            options.submodules = false;
+            options.compare = None;
+            options.since_base = None;
+            options.reflog_days = None;
+            options.diffstat = false;
+            options.with_hash = false;
+            options.with_tag = false;
+            options.unpushed_total = false;
+            options.with_sparse = false;
+            options.with_shallow = false;
+            options.with_tracked_count = false;
+            options.with_remote_count = false;
+            options.with_commit_age = false;
+            options.stash_details = false;
+            options.with_hidden_changes = false;
+            options.with_root = false;
+            options.git_args.clear();
+            options.cache_dir = None;
+        }
+
+        Ok(options)
+    }
+
+    /// Whether the field has to be computed, i.e. `--fields` isn't set or lists it
+    pub fn wants(&self, field: &str) -> bool {
+        self.fields
+            .as_ref()
+            .is_none_or(|fields| fields.iter().any(|wanted| wanted == field))
+    }
+
+    /// Fields of the line output in order: the `--fields` list, or every default field plus
+    /// the opt-in ones enabled by their flag. The line has exactly these positions, even when
+    /// some are left empty (e.g. in a bare repository).
+    pub fn output_fields(&self) -> Vec<&str> {
+        match &self.fields {
+            Some(fields) => fields.iter().map(String::as_str).collect(),
+            None => crate::Status::FIELDS
+                .into_iter()
+                .filter(|field| self.enables(field))
+                .collect(),
+        }
+    }
+
+    /// Whether the field is part of the default output, opt-in fields only with their flag
+    fn enables(&self, field: &str) -> bool {
+        match field {
+            "ignored" => self.show_ignored,
+            "untracked_dirs" => self.untracked_dirs,
+            "submodules_dirty" => self.submodules,
+            "compare_ahead" | "compare_behind" => self.compare.is_some(),
+            "since_base" => self.since_base.is_some(),
+            "reflog_count" => self.reflog_days.is_some(),
+            "dirty" => self.with_dirty,
+            "insertions" | "deletions" => self.diffstat,
+            "hash" => self.with_hash,
+            "unpushed_total" => self.unpushed_total,
+            "sparse" => self.with_sparse,
+            "shallow" => self.with_shallow,
+            "staged_clean" => self.with_staged_clean,
+            "ext_count" => self.ext_count,
+            "histogram" => self.histogram,
+            "warning" => !self.warn.is_empty(),
+            "tag" => self.with_tag,
+            "deleted_staged" | "deleted_unstaged" => self.split_deleted,
+            "root" | "root_name" => self.with_root,
+            "operation_state" => self.with_operation,
+            "branch" => !self.no_branch,
+            "divergence" => self.net_divergence,
+            "partially_staged" => self.partially_staged,
+            "tracked" => self.with_tracked_count,
+            "remote_count" => self.with_remote_count,
+            "commit_age" => self.with_commit_age,
+            "stash_subject" => self.stash_details,
+            "assume_unchanged" | "skip_worktree" => self.with_hidden_changes,
+            "added" => self.added,
+            "unstaged_total" => self.unstaged_total,
+            field if CONFLICT_FIELDS.contains(&field) => self.conflict_breakdown,
+            _ => true,
+        }
+    }
+
+    /// Whether the field has to be read, for the output or for what `--only-if-dirty`,
+    /// `--quiet`, `--with-operation`, `--fail-on-conflict` and `--warn-<count>` are made of.
+    /// `--no-branch` spares naming the branch altogether.
+    pub fn needs(&self, field: &str) -> bool {
+        if field == "branch" && self.no_branch {
+            return false;
+        }
+        self.wants(field)
+            || (self.only_if_dirty || self.quiet)
+                && (field == "clean" || field == "stashed" && self.dirty_includes("stash"))
+            || self.with_operation && matches!(field, "operation" | "step" | "conflicts")
+            || self.fail_on_conflict && field == "conflicts"
+            || self.warn.iter().any(|(warned, _)| warned == field)
+    }
+
+    /// Whether ahead, behind or stash make the repository count as dirty for `--only-if-dirty`
+    /// and `--quiet`
+    pub fn dirty_includes(&self, what: &str) -> bool {
+        self.dirty_includes
+            .as_ref()
+            .is_none_or(|includes| includes.iter().any(|included| included == what))
+    }
+}
+
+fn parse_fields(fields: &str) -> Result<Vec<String>, String> {
+    fields
+        .split(',')
+        .map(|field| match field.trim() {
+            field if crate::Status::FIELDS.contains(&field) => Ok(field.to_string()),
+            field => Err(format!("unknown field '{}'", field)),
+        })
+        .collect()
+}
+
+fn parse_repos(repos: &str) -> Vec<String> {
+    repos
+        .split(',')
+        .filter(|repo| !repo.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Resolves `auto` right away, so the rest only has to know whether to paint
+fn parse_color(when: &str) -> Result<bool, String> {
+    match when {
+        "auto" => Ok(std::io::stdout().is_terminal()),
+        "always" => Ok(true),
+        "never" => Ok(false),
+        _ => Err(format!("unknown color mode '{}'", when)),
+    }
+}
+
+fn parse_len(len: &str) -> Result<usize, String> {
+    // nothing would be left of the branch but the `…`
+    match len.parse() {
+        Ok(len) if len > 0 => Ok(len),
+        _ => Err(format!("invalid branch length '{}' (at least 1)", len)),
+    }
+}
+
+fn parse_retries(retries: &str) -> Result<u32, String> {
+    // the backoff doubles every time, more than a handful would be waiting for seconds
+    match retries.parse() {
+        Ok(retries) if retries <= 10 => Ok(retries),
+        _ => Err(format!("invalid number of retries '{}' (0 to 10)", retries)),
+    }
+}
+
+fn parse_days(days: &str) -> Result<u64, String> {
+    days.parse()
+        .map_err(|_| format!("invalid number of days '{}'", days))
+}
+
+fn is_warn_flag(flag: &str) -> bool {
+    flag.strip_prefix("--warn-")
+        .is_some_and(|field| WARN_FIELDS.contains(&field))
+}
+
+fn parse_threshold(threshold: &str) -> Result<usize, String> {
+    threshold
+        .parse()
+        .map_err(|_| format!("invalid threshold '{}'", threshold))
+}
+
+fn parse_cap(cap: &str) -> Result<u64, String> {
+    cap.parse().map_err(|_| format!("invalid cap '{}'", cap))
+}
+
+fn parse_ms(ms: &str) -> Result<Duration, String> {
+    ms.parse()
+        .map(Duration::from_millis)
+        .map_err(|_| format!("invalid number of milliseconds '{}'", ms))
+}
+
+fn parse_encoding(encoding: &str) -> Result<Encoding, String> {
+    match encoding {
+        "none" => Ok(Encoding::None),
+        "url" => Ok(Encoding::Url),
+        "shell" => Ok(Encoding::Shell),
+        _ => Err(format!("unknown encoding '{}'", encoding)),
+    }
+}
+
+fn parse_untracked_mode(mode: &str) -> Result<UntrackedMode, String> {
+    match mode {
+        "normal" => Ok(UntrackedMode::Normal),
+        "all" => Ok(UntrackedMode::All),
+        "no" => Ok(UntrackedMode::No),
+        _ => Err(format!("unknown untracked mode '{}'", mode)),
+    }
+}
+
+/// Splits the extra git status arguments on whitespace, refusing the ones that would change the
+/// output format the parsers rely on
+fn parse_git_args(args: &str) -> Result<Vec<String>, String> {
+    const FORMAT_ARGS: [&str; 13] = [
+        "--porcelain",
+        "--short",
+        "-s",
+        "--long",
+        "--branch",
+        "-b",
+        "--no-branch",
+        "-z",
+        "--null",
+        "--verbose",
+        "-v",
+        "--column",
+        "--no-column",
+    ];
+    args.split_whitespace()
+        .map(|arg| {
+            let name = arg.split_once('=').map_or(arg, |(name, _)| name);
+            if FORMAT_ARGS.contains(&name) {
+                Err(format!(
+                    "--git-args can't change the output format ('{}')",
+                    arg
+                ))
+            } else {
+                Ok(arg.to_string())
+            }
+        })
+        .collect()
+}
+
+/// An empty list leaves only the working tree changes
+fn parse_dirty_includes(includes: &str) -> Result<Vec<String>, String> {
+    includes
+        .split(',')
+        .map(str::trim)
+        .filter(|include| !include.is_empty())
+        .map(|include| match include {
+            "ahead" | "behind" | "stash" => Ok(include.to_string()),
+            _ => Err(format!("unknown --dirty-includes value '{}'", include)),
+        })
+        .collect()
+}
+
+fn parse_porcelain(version: &str) -> Result<PorcelainVersion, String> {
+    match version {
+        "1" => Ok(PorcelainVersion::V1),
+        "2" => Ok(PorcelainVersion::V2),
+        "auto" => Ok(PorcelainVersion::Auto),
+        _ => Err(format!("unknown porcelain version '{}'", version)),
+    }
+}
+
+fn parse_backend(backend: &str) -> Result<Backend, String> {
+    match backend {
+        "subprocess" => Ok(Backend::Subprocess),
+        "libgit2" => Ok(Backend::Libgit2),
+        _ => Err(format!("unknown backend '{}'", backend)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(env: &[(&str, &str)], args: &[&str]) -> Result<Options, String> {
+        Options::from_env_and_args(
+            env.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+            args.iter().map(|arg| arg.to_string()),
+        )
+    }
+
+    #[test]
+    fn environment_alone_sets_the_defaults() {
+        let options = parse(
+            &[
+                ("GITSTATUS_NO_UNTRACKED", "1"),
+                ("GITSTATUS_MAX_BRANCH_LEN", "20"),
+                ("GITSTATUS_JSON", "yes"),
+                ("GITSTATUS_QUIET", "0"),
+                ("OTHER", "1"),
+            ],
+            &[],
+        )
+        .unwrap();
+        assert!(options.untracked_mode == Some(UntrackedMode::No));
+        assert_eq!(options.max_branch_len, Some(20));
+        assert!(options.json);
+        assert!(!options.quiet);
+    }
+
+    #[test]
+    fn environment_format_is_used_without_an_output_flag() {
+        let options = parse(&[("GITSTATUS_FORMAT", "{branch}")], &[]).unwrap();
+        assert_eq!(options.format.as_deref(), Some("{branch}"));
+        assert_eq!(options.output_fields(), ["branch"]);
+    }
+
+    #[test]
+    fn invalid_environment_switch_is_an_error() {
+        assert!(parse(&[("GITSTATUS_NULL", "maybe")], &[]).is_err());
+    }
+
+    #[test]
+    fn command_line_short_replaces_environment_json() {
+        let options = parse(&[("GITSTATUS_JSON", "1")], &["--short"]).unwrap();
+        assert!(options.short);
+        assert!(!options.json);
+    }
+
+    #[test]
+    fn command_line_format_replaces_environment_fields() {
+        let options = parse(
+            &[("GITSTATUS_FIELDS", "branch")],
+            &["--format", "{changed} {untracked}"],
+        )
+        .unwrap();
+        assert_eq!(options.output_fields(), ["changed", "untracked"]);
+    }
+
+    #[test]
+    fn command_line_value_wins_over_the_environment() {
+        let options = parse(
+            &[("GITSTATUS_MAX_BRANCH_LEN", "20")],
+            &["--max-branch-len=5"],
+        )
+        .unwrap();
+        assert_eq!(options.max_branch_len, Some(5));
+    }
+
+    #[test]
+    fn environment_switches_outside_the_output_ones_add_up() {
+        let options = parse(&[("GITSTATUS_NULL", "1")], &["--json"]).unwrap();
+        assert!(options.json);
+        assert!(options.null);
+    }
+
+    #[test]
+    fn command_line_git_args_replace_the_environment_ones() {
+        let options = parse(&[("GITSTATUS_GIT_ARGS", "-- new")], &[]).unwrap();
+        assert_eq!(options.git_args, ["--", "new"]);
+
+        let options = parse(&[("GITSTATUS_GIT_ARGS", "-- new")], &["--git-args", "-- f"]).unwrap();
+        assert_eq!(options.git_args, ["--", "f"]);
+
+        // on the command line alone they still add up
+        let options = parse(&[], &["--git-args=--", "--git-args", "f"]).unwrap();
+        assert_eq!(options.git_args, ["--", "f"]);
+    }
+
+    #[test]
+    fn since_base_ref_is_optional() {
+        let base = |args: &[&str]| parse(&[], args).unwrap().since_base;
+        assert_eq!(base(&["--since-base"]).as_deref(), Some("origin/HEAD"));
+        assert_eq!(
+            base(&["--since-base", "--json"]).as_deref(),
+            Some("origin/HEAD")
+        );
+        assert_eq!(base(&["--since-base", "main"]).as_deref(), Some("main"));
+        assert_eq!(base(&["--since-base=main"]).as_deref(), Some("main"));
+        assert_eq!(
+            base(&["--fields", "since_base"]).as_deref(),
+            Some("origin/HEAD")
+        );
+        assert_eq!(base(&[]), None);
+    }
+
+    #[test]
+    fn max_branch_len_of_zero_is_an_error() {
+        assert!(parse(&[], &["--max-branch-len", "0"]).is_err());
+        assert!(parse(&[("GITSTATUS_MAX_BRANCH_LEN", "0")], &[]).is_err());
+        let options = parse(&[], &["--max-branch-len", "1"]).unwrap();
+        assert_eq!(options.max_branch_len, Some(1));
+    }
+}
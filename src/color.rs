@@ -0,0 +1,29 @@
+//! ANSI colors for `--color`
+//!
+//! Only the counts of pending work are painted, and only when they're not zero. Each color is
+//! an SGR parameter list that can be overridden through `GITSTATUS_COLOR_<FIELD>`, e.g.
+//! `GITSTATUS_COLOR_STAGED="1;32"`, or the `[colors]` of the `--config` file.
+
+use crate::config::Config;
+
+/// Default colors: red for conflicts, green for staged, yellow for changes, cyan for untracked
+pub(crate) const PALETTE: [(&str, &str); 5] = [
+    ("conflicts", "31"),
+    ("staged", "32"),
+    ("changed", "33"),
+    ("deleted", "33"),
+    ("untracked", "36"),
+];
+
+/// Wraps the value of the field in its color, or returns it as is
+pub fn paint(name: &str, value: String, config: &Config) -> String {
+    let Some(&(_, default)) = PALETTE.iter().find(|(field, _)| *field == name) else {
+        return value;
+    };
+    if value == "0" {
+        return value;
+    }
+
+    let color = config.color(name).unwrap_or_else(|| default.to_string());
+    format!("\x1b[{}m{}\x1b[0m", color, value)
+}
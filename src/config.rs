@@ -0,0 +1,76 @@
+//! `--config`: glyphs, colors and a default template from a TOML file
+//!
+//! ```toml
+//! format = "{branch} +{staged}"
+//!
+//! [glyphs]
+//! ahead = "⇡"
+//!
+//! [colors]
+//! staged = "1;32"
+//! ```
+//!
+//! The environment (`GITSTATUS_GLYPH_<FIELD>`, `GITSTATUS_COLOR_<FIELD>`) overrides the file,
+//! and the output flags on the command line override both.
+
+use serde::Deserialize;
+use std::{collections::HashMap, fs};
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Template used when no other output flag (`--format`, `--json`, `--short`, `--fields`)
+    /// is given
+    pub format: Option<String>,
+    /// `--short` glyphs by field
+    pub glyphs: HashMap<String, String>,
+    /// `--color` SGR parameters by field
+    pub colors: HashMap<String, String>,
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("couldn't read config '{}': {}", path, error))?;
+        let config: Config = toml::from_str(&text)
+            .map_err(|error| format!("invalid config '{}': {}", path, error.message()))?;
+
+        // a typo would otherwise be silently ignored
+        if let Some(name) = config
+            .glyphs
+            .keys()
+            .find(|name| !crate::GLYPHS.iter().any(|(field, _)| field == name))
+        {
+            return Err(format!(
+                "invalid config '{}': no glyph for '{}'",
+                path, name
+            ));
+        }
+        if let Some(name) = config
+            .colors
+            .keys()
+            .find(|name| !crate::color::PALETTE.iter().any(|(field, _)| field == name))
+        {
+            return Err(format!(
+                "invalid config '{}': no color for '{}'",
+                path, name
+            ));
+        }
+
+        Ok(config)
+    }
+
+    /// The `--short` glyph of the field, if the environment or the file sets one
+    pub fn glyph(&self, name: &str) -> Option<String> {
+        std::env::var(format!("GITSTATUS_GLYPH_{}", name.to_uppercase()))
+            .ok()
+            .or_else(|| self.glyphs.get(name).cloned())
+    }
+
+    /// The `--color` SGR parameters of the field, if the environment or the file sets them
+    pub fn color(&self, name: &str) -> Option<String> {
+        std::env::var(format!("GITSTATUS_COLOR_{}", name.to_uppercase()))
+            .ok()
+            .or_else(|| self.colors.get(name).cloned())
+    }
+}
@@ -0,0 +1,1989 @@
+//! Status of a git repository, as printed by the `gitstatus` binary
+//!
+//! [`gather`] reads the repository (through `git status` or libgit2) into a [`Status`], and
+//! [`format_output`] renders it like the command line does.
+
+pub mod binary;
+pub mod cache;
+pub mod cli;
+mod color;
+pub mod config;
+#[cfg(feature = "libgit2")]
+mod libgit2;
+mod porcelain_v1;
+mod porcelain_v2;
+pub mod selftest;
+pub mod watch;
+
+pub use cli::Options;
+use cli::{Encoding, PorcelainVersion, UntrackedMode};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    fs::File,
+    io::{self, BufRead, Read, Write},
+    path::Path,
+    process::{Command, Output, Stdio},
+    result::Result,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A status entry: index (X) and worktree (Y) state, and its path
+pub type StatusLine = (char, char, String);
+
+/// Fields derived from the status entries, as opposed to the branch header
+const ENTRY_FIELDS: [&str; 26] = [
+    "staged",
+    "conflicts",
+    "changed",
+    "untracked",
+    "clean",
+    "deleted",
+    "renamed",
+    "copied",
+    "ignored",
+    "untracked_dirs",
+    "dirty",
+    "staged_clean",
+    "ext_count",
+    "deleted_staged",
+    "deleted_unstaged",
+    "partially_staged",
+    "added",
+    "both_deleted",
+    "added_by_us",
+    "deleted_by_them",
+    "added_by_them",
+    "deleted_by_us",
+    "both_added",
+    "both_modified",
+    "unstaged_total",
+    "histogram",
+];
+
+#[derive(Default, Serialize)]
+pub struct Status {
+    pub branch: String,
+    pub ahead: usize,
+    pub behind: usize,
+    pub staged: usize,
+    pub conflicts: usize,
+    pub changed: usize,
+    pub untracked: usize,
+    pub stashed: usize,
+    pub clean: bool,
+    pub deleted: usize,
+    pub renamed: usize,
+    pub copied: usize,
+    pub operation: String,
+    pub step: String,
+    pub detached: bool,
+    pub upstream: String,
+    pub no_upstream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ignored: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub untracked_dirs: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub submodules_dirty: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compare_ahead: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compare_behind: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dirty: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insertions: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deletions: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unpushed_total: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sparse: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub staged_clean: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ext_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_staged: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_unstaged: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub divergence: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partially_staged: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tracked: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_age: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub added: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub both_deleted: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub added_by_us: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_by_them: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub added_by_them: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_by_us: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub both_added: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub both_modified: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assume_unchanged: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_worktree: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since_base: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shallow: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reflog_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operation_state: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unstaged_total: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stash_subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_count: Option<usize>,
+    /// Entries by their `XY` state, `" M"` as git writes it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub histogram: Option<BTreeMap<String, usize>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<bool>,
+}
+
+impl Status {
+    /// Fields of the default output, in order. Opt-in fields are only part of the output when
+    /// their flag is set.
+    pub const FIELDS: [&'static str; 58] = [
+        "branch",
+        "ahead",
+        "behind",
+        "staged",
+        "conflicts",
+        "changed",
+        "untracked",
+        "stashed",
+        "clean",
+        "deleted",
+        "renamed",
+        "copied",
+        "operation",
+        "step",
+        "detached",
+        "upstream",
+        "no_upstream",
+        "ignored",
+        "untracked_dirs",
+        "submodules_dirty",
+        "compare_ahead",
+        "compare_behind",
+        "dirty",
+        "insertions",
+        "deletions",
+        "hash",
+        "unpushed_total",
+        "sparse",
+        "staged_clean",
+        "ext_count",
+        "tag",
+        "deleted_staged",
+        "deleted_unstaged",
+        "root",
+        "root_name",
+        "divergence",
+        "partially_staged",
+        "tracked",
+        "commit_age",
+        "added",
+        "both_deleted",
+        "added_by_us",
+        "deleted_by_them",
+        "added_by_them",
+        "deleted_by_us",
+        "both_added",
+        "both_modified",
+        "assume_unchanged",
+        "skip_worktree",
+        "since_base",
+        "shallow",
+        "reflog_count",
+        "operation_state",
+        "unstaged_total",
+        "stash_subject",
+        "remote_count",
+        "histogram",
+        "warning",
+    ];
+
+    /// Looks up a field by its name, formatted the same way as in the default output
+    pub fn field(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "branch" => self.branch.clone(),
+            "ahead" => self.ahead.to_string(),
+            "behind" => self.behind.to_string(),
+            "staged" => self.staged.to_string(),
+            "conflicts" => self.conflicts.to_string(),
+            "changed" => self.changed.to_string(),
+            "untracked" => self.untracked.to_string(),
+            "stashed" => self.stashed.to_string(),
+            "clean" => (self.clean as i32).to_string(),
+            "deleted" => self.deleted.to_string(),
+            "renamed" => self.renamed.to_string(),
+            "copied" => self.copied.to_string(),
+            "operation" => self.operation.clone(),
+            "step" => self.step.clone(),
+            "detached" => (self.detached as i32).to_string(),
+            "upstream" => self.upstream.clone(),
+            "no_upstream" => (self.no_upstream as i32).to_string(),
+            "ignored" => self.ignored?.to_string(),
+            "untracked_dirs" => self.untracked_dirs?.to_string(),
+            "submodules_dirty" => self.submodules_dirty?.to_string(),
+            "compare_ahead" => self.compare_ahead?.to_string(),
+            "compare_behind" => self.compare_behind?.to_string(),
+            "dirty" => (self.dirty? as i32).to_string(),
+            "insertions" => self.insertions?.to_string(),
+            "deletions" => self.deletions?.to_string(),
+            "hash" => self.hash.clone()?,
+            "unpushed_total" => self.unpushed_total?.to_string(),
+            "sparse" => (self.sparse? as i32).to_string(),
+            "staged_clean" => (self.staged_clean? as i32).to_string(),
+            "ext_count" => self.ext_count?.to_string(),
+            "tag" => self.tag.clone()?,
+            "deleted_staged" => self.deleted_staged?.to_string(),
+            "deleted_unstaged" => self.deleted_unstaged?.to_string(),
+            "root" => self.root.clone()?,
+            "root_name" => self.root_name.clone()?,
+            "divergence" => self.divergence?.to_string(),
+            "partially_staged" => self.partially_staged?.to_string(),
+            "tracked" => self.tracked?.to_string(),
+            "commit_age" => self.commit_age.clone()?,
+            "added" => self.added?.to_string(),
+            "both_deleted" => self.both_deleted?.to_string(),
+            "added_by_us" => self.added_by_us?.to_string(),
+            "deleted_by_them" => self.deleted_by_them?.to_string(),
+            "added_by_them" => self.added_by_them?.to_string(),
+            "deleted_by_us" => self.deleted_by_us?.to_string(),
+            "both_added" => self.both_added?.to_string(),
+            "both_modified" => self.both_modified?.to_string(),
+            "assume_unchanged" => self.assume_unchanged?.to_string(),
+            "skip_worktree" => self.skip_worktree?.to_string(),
+            "since_base" => self.since_base?.to_string(),
+            "shallow" => (self.shallow? as i32).to_string(),
+            "reflog_count" => self.reflog_count?.to_string(),
+            "operation_state" => self.operation_state.clone()?,
+            "unstaged_total" => self.unstaged_total?.to_string(),
+            "stash_subject" => self.stash_subject.clone()?,
+            "remote_count" => self.remote_count?.to_string(),
+            "warning" => (self.warning? as i32).to_string(),
+            // `.M=3,??=5`: an unchanged side is a `.` like in porcelain v2, so there's no space
+            "histogram" => self
+                .histogram
+                .as_ref()?
+                .iter()
+                .map(|(state, count)| format!("{}={}", state.replace(' ', "."), count))
+                .collect::<Vec<_>>()
+                .join(","),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Repository state read by one of the backends, before its entries are classified
+#[derive(Default)]
+struct Snapshot {
+    branch: Option<String>,
+    upstream: Option<String>,
+    no_upstream: bool,
+    ahead: usize,
+    behind: usize,
+    lines: Vec<StatusLine>,
+    stashed: usize,
+    detached: bool,
+    git_dir: Option<String>,
+}
+
+/// Branch details read from the porcelain header (`##` in v1, `# branch.*` in v2)
+#[derive(Default)]
+struct Branch {
+    head: Option<String>,
+    detached: bool,
+    initial: bool,
+    upstream: Option<String>,
+    ahead: usize,
+    behind: usize,
+}
+
+impl Snapshot {
+    /// A snapshot of what `git status` reported, the lookups it can't tell are left unset
+    fn from_porcelain(branch: Branch, lines: Vec<StatusLine>) -> Snapshot {
+        Snapshot {
+            no_upstream: !branch.detached && !branch.initial && branch.upstream.is_none(),
+            branch: branch.head,
+            upstream: branch.upstream,
+            ahead: branch.ahead,
+            behind: branch.behind,
+            detached: branch.detached,
+            lines,
+            ..Default::default()
+        }
+    }
+}
+
+/// Why the repository couldn't be read
+#[derive(Debug)]
+pub enum Error {
+    /// not inside a git repository, or no git to run at all
+    NotARepo,
+    /// a bare repository, which has a branch but no work tree to report on
+    Bare,
+    /// `git status` didn't finish within `--timeout-ms`
+    TimedOut,
+    /// another git process held the index lock through every `--retries`
+    Locked,
+    /// git ran and failed for any other reason, with its error message
+    Git(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotARepo => write!(f, "not a git repository"),
+            Error::Bare => write!(f, "bare repository"),
+            Error::TimedOut => write!(f, "git status timed out"),
+            Error::Locked => write!(f, "the index is locked by another git process"),
+            Error::Git(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Whether the repository is searched from the current directory, `--directory` being relative
+/// to it as well
+fn needs_cwd(options: &Options) -> bool {
+    options
+        .directory
+        .as_deref()
+        .is_none_or(|directory| Path::new(directory).is_relative())
+}
+
+/// Reads the repository through the selected backend and classifies its entries
+pub fn gather(options: &Options) -> Result<Status, Error> {
+    let snapshot = match options.backend {
+        // nothing to look up besides what was captured, see `Options::from_args`
+        _ if options.stdin => Ok(read_snapshot(io::stdin().lock(), options.porcelain, None)),
+        // the current directory was removed from under us (e.g. by a checkout), a prompt should
+        // render nothing there rather than fail
+        _ if needs_cwd(options) && std::env::current_dir().is_err() => Err(Error::NotARepo),
+        #[cfg(feature = "libgit2")]
+        cli::Backend::Libgit2 => timed(options, "libgit2", || libgit2::read(options)),
+        // without the libgit2 feature every backend spawns git
+        _ => read_porcelain(options),
+    }?;
+
+    let git_dir = snapshot.git_dir.clone();
+    let mut status = tally(snapshot);
+
+    let (operation, step) = git_dir
+        .as_deref()
+        .and_then(get_operation_state)
+        .unwrap_or_default();
+
+    let compare = options
+        .compare
+        .as_deref()
+        .map(|target| get_divergence(options, target));
+
+    let diffstat = options.diffstat.then(|| get_diffstat(options));
+
+    status.operation = operation.to_string();
+    status.step = step.unwrap_or_default();
+    // `rebase 3/7 conflicts=2`: whether the operation can go on, empty when there is none
+    status.operation_state = options.with_operation.then(|| match operation {
+        "" => String::new(),
+        operation => [
+            operation,
+            &status.step,
+            &format!("conflicts={}", status.conflicts),
+        ]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" "),
+    });
+    // the entries tell these for free, but they're only part of the output when asked for
+    status.ignored = status.ignored.filter(|_| options.show_ignored);
+    status.untracked_dirs = status.untracked_dirs.filter(|_| options.untracked_dirs);
+    status.staged_clean = status.staged_clean.filter(|_| options.with_staged_clean);
+    status.ext_count = status.ext_count.filter(|_| options.ext_count);
+    status.histogram = status.histogram.filter(|_| options.histogram);
+    status.deleted_staged = status.deleted_staged.filter(|_| options.split_deleted);
+    status.deleted_unstaged = status.deleted_unstaged.filter(|_| options.split_deleted);
+    status.partially_staged = status.partially_staged.filter(|_| options.partially_staged);
+    status.added = status.added.filter(|_| options.added);
+    status.unstaged_total = status.unstaged_total.filter(|_| options.unstaged_total);
+    status.both_deleted = status.both_deleted.filter(|_| options.conflict_breakdown);
+    status.added_by_us = status.added_by_us.filter(|_| options.conflict_breakdown);
+    status.deleted_by_them = status
+        .deleted_by_them
+        .filter(|_| options.conflict_breakdown);
+    status.added_by_them = status.added_by_them.filter(|_| options.conflict_breakdown);
+    status.deleted_by_us = status.deleted_by_us.filter(|_| options.conflict_breakdown);
+    status.both_added = status.both_added.filter(|_| options.conflict_breakdown);
+    status.both_modified = status.both_modified.filter(|_| options.conflict_breakdown);
+    status.submodules_dirty = options.submodules.then(|| get_dirty_submodules(options));
+    status.compare_ahead = compare.map(|(ahead, _)| ahead);
+    status.compare_behind = compare.map(|(_, behind)| behind);
+    status.dirty = options.with_dirty.then_some(!status.clean);
+    status.since_base = options
+        .since_base
+        .as_deref()
+        .map(|base| count_since_base(options, base));
+    // negative when the branch is more behind than ahead
+    status.divergence = options
+        .net_divergence
+        .then_some(status.ahead as i64 - status.behind as i64);
+    status.insertions = diffstat.map(|(insertions, _)| insertions);
+    status.deletions = diffstat.map(|(_, deletions)| deletions);
+    status.hash = options
+        .with_hash
+        .then(|| short_head(options).unwrap_or_default());
+    status.unpushed_total = options.unpushed_total.then(|| get_unpushed_total(options));
+    status.sparse = options.with_sparse.then(|| is_sparse(options));
+    status.shallow = options.with_shallow.then(|| {
+        // git keeps the boundary commits of a shallow clone in `$GIT_DIR/shallow`
+        let shallow = git_dir
+            .clone()
+            .or_else(|| get_git_dir(options))
+            .is_some_and(|git_dir| Path::new(&git_dir).join("shallow").exists());
+        if shallow && options.verbose {
+            eprintln!("gitstatus: shallow clone, ahead and behind may be off");
+        }
+        shallow
+    });
+    status.tracked = options.with_tracked_count.then(|| count_tracked(options));
+    status.remote_count = options.with_remote_count.then(|| count_remotes(options));
+    let hidden = options.with_hidden_changes.then(|| count_hidden(options));
+    status.assume_unchanged = hidden.map(|(assume_unchanged, _)| assume_unchanged);
+    status.skip_worktree = hidden.map(|(_, skip_worktree)| skip_worktree);
+    status.reflog_count = options.reflog_days.map(|days| count_reflog(options, days));
+    status.commit_age = options
+        .with_commit_age
+        .then(|| get_commit_age(options).unwrap_or_default());
+    status.stash_subject = options
+        .stash_details
+        .then(|| get_stash_subject(options).unwrap_or_default());
+    status.tag = options
+        .with_tag
+        .then(|| points_at_tag(options).unwrap_or_default());
+    if options.with_root {
+        let root = get_toplevel(options).unwrap_or_default();
+        status.root_name = Some(
+            Path::new(&root)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        );
+        status.root = Some(root);
+    }
+    status.branch = encode_branch(shorten_branch(status.branch, options), options);
+    status.warning = (!options.warn.is_empty()).then(|| {
+        options.warn.iter().any(|(field, threshold)| {
+            status
+                .field(field)
+                .and_then(|count| count.parse::<usize>().ok())
+                .is_some_and(|count| count > *threshold)
+        })
+    });
+
+    Ok(status)
+}
+
+/// Parses `git status --porcelain=v1 --branch -z` output into a status, without running git.
+/// [`gather`] feeds git's own output through the same parser.
+///
+/// Only what the porcelain tells is filled in: a detached HEAD has an empty branch, and the
+/// stashes and the opt-in lookups that need git are left unset.
+pub fn parse_porcelain(input: impl BufRead) -> Status {
+    tally(read_snapshot(input, PorcelainVersion::V1, None))
+}
+
+/// Parses porcelain output into a snapshot, `detached` overriding what the header tells
+fn read_snapshot(
+    mut input: impl BufRead,
+    porcelain: PorcelainVersion,
+    detached: Option<bool>,
+) -> Snapshot {
+    let mut stdout = vec![];
+    // whatever was read before an error is still worth reporting
+    let _ = input.read_to_end(&mut stdout);
+    let porcelain_v2 = match porcelain {
+        PorcelainVersion::V1 => false,
+        PorcelainVersion::V2 => true,
+        // v2 starts with its `# branch.*` headers, or with an entry type and a space
+        PorcelainVersion::Auto => {
+            stdout.starts_with(b"# ") || matches!(stdout.get(..2), Some([b'1' | b'2' | b'u', b' ']))
+        }
+    };
+    let (mut branch, lines) = if porcelain_v2 {
+        porcelain_v2::parse(&stdout)
+    } else {
+        porcelain_v1::parse(&stdout)
+    };
+    if let Some(detached) = detached {
+        branch.detached = detached;
+    }
+    Snapshot::from_porcelain(branch, lines)
+}
+
+/// Classifies the entries of the snapshot into the status counts
+fn tally(snapshot: Snapshot) -> Status {
+    let ext_count = count_extensions(&snapshot.lines);
+    let histogram = count_by_state(&snapshot.lines);
+    let mut untracked: Vec<StatusLine> = vec![];
+    let mut staged: Vec<StatusLine> = vec![];
+    let mut changed: Vec<StatusLine> = vec![];
+    let mut deleted: Vec<StatusLine> = vec![];
+    let mut conflicts: Vec<StatusLine> = vec![];
+    let mut ignored: Vec<StatusLine> = vec![];
+    let mut renamed = 0;
+    let mut copied = 0;
+    let mut deleted_staged = 0;
+    let mut partially_staged = 0;
+    let mut added = 0;
+    let mut unstaged_total = 0;
+
+    for status in snapshot.lines {
+        if status.0 == 'R' || status.1 == 'R' {
+            renamed += 1;
+        } else if status.0 == 'C' || status.1 == 'C' {
+            copied += 1;
+        }
+
+        match status {
+            ('?', '?', _) => untracked.push(status),
+            // only reported by git when running with `--show-ignored`
+            ('!', '!', _) => ignored.push(status),
+            // every unmerged pair from `git status --help`, before `AA`/`DD` reach the
+            // staged/deleted arm
+            ('D', 'D', _) | ('A', 'A', _) | ('U', _, _) | (_, 'U', _) => conflicts.push(status),
+            // the index (X) and worktree (Y) columns are independent, so e.g. `MM`
+            // counts both as staged and as changed
+            (index, worktree, _) => {
+                if index != ' ' {
+                    staged.push(status.clone());
+                }
+                // pending on both sides (`MM`, `AM`, `RM`...), still counted in staged and
+                // changed/deleted as well
+                if index != ' ' && worktree != ' ' {
+                    partially_staged += 1;
+                }
+                // new files with nothing else pending, a subset of staged
+                if (index, worktree) == ('A', ' ') {
+                    added += 1;
+                }
+                // any change not staged yet, once per file whatever the index side is
+                if worktree != ' ' {
+                    unstaged_total += 1;
+                }
+                // still counted as staged, `deleted` only has the worktree side
+                if index == 'D' {
+                    deleted_staged += 1;
+                }
+                match worktree {
+                    'M' => changed.push(status),
+                    'D' => deleted.push(status),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let clean = is_clean(&changed, &deleted, &staged, &conflicts, &untracked);
+
+    Status {
+        branch: snapshot.branch.unwrap_or_default(),
+        ahead: snapshot.ahead,
+        behind: snapshot.behind,
+        staged: staged.len(),
+        conflicts: conflicts.len(),
+        changed: changed.len(),
+        untracked: untracked.len(),
+        stashed: snapshot.stashed,
+        clean,
+        deleted: deleted.len(),
+        renamed,
+        copied,
+        detached: snapshot.detached,
+        upstream: snapshot.upstream.unwrap_or_default(),
+        no_upstream: snapshot.no_upstream,
+        ignored: Some(ignored.len()),
+        untracked_dirs: Some(count_top_level_dirs(&untracked)),
+        // ready to commit: everything pending is staged
+        staged_clean: Some(
+            !staged.is_empty()
+                && changed.is_empty()
+                && untracked.is_empty()
+                && conflicts.is_empty()
+                && deleted.is_empty(),
+        ),
+        ext_count: Some(ext_count),
+        histogram: Some(histogram),
+        deleted_staged: Some(deleted_staged),
+        deleted_unstaged: Some(deleted.len()),
+        partially_staged: Some(partially_staged),
+        added: Some(added),
+        unstaged_total: Some(unstaged_total),
+        both_deleted: Some(count_conflicts(&conflicts, ('D', 'D'))),
+        added_by_us: Some(count_conflicts(&conflicts, ('A', 'U'))),
+        deleted_by_them: Some(count_conflicts(&conflicts, ('U', 'D'))),
+        added_by_them: Some(count_conflicts(&conflicts, ('U', 'A'))),
+        deleted_by_us: Some(count_conflicts(&conflicts, ('D', 'U'))),
+        both_added: Some(count_conflicts(&conflicts, ('A', 'A'))),
+        both_modified: Some(count_conflicts(&conflicts, ('U', 'U'))),
+        ..Default::default()
+    }
+}
+
+/// What's left when git status can't report entries (out of time, bare repository): the
+/// (cheap) branch name with zeroed counts. A bare repository is clean, nothing in it can be
+/// dirty, while a read that didn't finish isn't known to be: the same rule `--quiet` exits by.
+pub fn branch_only_status(options: &Options, error: &Error) -> Status {
+    Status {
+        clean: matches!(error, Error::Bare),
+        branch: encode_branch(
+            shorten_branch(get_symbolic_branch(options).unwrap_or_default(), options),
+            options,
+        ),
+        tracked: options.with_tracked_count.then_some(0),
+        warning: (!options.warn.is_empty()).then_some(false),
+        ..Default::default()
+    }
+}
+
+/// Applies `--branch-abbrev` (`feature/foo/bar` becomes `f/f/bar`) and then `--max-branch-len`,
+/// once the branch, tag or hash is resolved
+fn shorten_branch(branch: String, options: &Options) -> String {
+    let branch = if options.branch_abbrev {
+        let mut parts = branch.split('/').collect::<Vec<_>>();
+        let last = parts.pop().unwrap_or_default();
+        parts
+            .iter()
+            .filter_map(|part| part.chars().next())
+            .map(|initial| format!("{}/", initial))
+            .chain(std::iter::once(last.to_string()))
+            .collect()
+    } else {
+        branch
+    };
+
+    match options.max_branch_len {
+        // counted in chars so multi-byte names aren't cut in the middle of one
+        Some(max) if branch.chars().count() > max => {
+            let mut short = branch
+                .chars()
+                .take(max.saturating_sub(1))
+                .collect::<String>();
+            short.push('…');
+            short
+        }
+        _ => branch,
+    }
+}
+
+/// Applies `--encode` to the shortened branch, so the escaping is never cut
+fn encode_branch(branch: String, options: &Options) -> String {
+    match options.encode {
+        Encoding::None => branch,
+        // RFC 3986 unreserved characters stay, every other byte of the UTF-8 becomes `%XX`
+        Encoding::Url => branch
+            .bytes()
+            .map(|byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    (byte as char).to_string()
+                }
+                _ => format!("%{:02X}", byte),
+            })
+            .collect(),
+        Encoding::Shell => format!("'{}'", branch.replace('\'', "'\\''")),
+    }
+}
+
+/// Renders the status the way a run that can't fail does (`--watch`, `--repos`): degraded to
+/// the branch when git status can't tell more, and `None` when git failed (reported on stderr)
+/// or the index stayed locked
+pub fn render(options: &Options) -> Result<Option<Vec<u8>>, serde_json::Error> {
+    let out = match gather(options) {
+        Ok(status) => format_bytes(&status, options)?,
+        Err(error @ (Error::TimedOut | Error::Bare)) => {
+            format_bytes(&branch_only_status(options, &error), options)?
+        }
+        Err(Error::NotARepo) if options.json => b"{}".to_vec(),
+        Err(Error::NotARepo) => Vec::new(),
+        Err(Error::Locked) => return Ok(None),
+        Err(Error::Git(error)) => {
+            eprintln!("gitstatus: {}", error);
+            return Ok(None);
+        }
+    };
+
+    Ok(Some(out))
+}
+
+/// Writes the rendered output to `--output` (truncated first, a named pipe works too) or to
+/// stdout, the one sink every output goes through
+pub fn write_output(options: &Options, out: &[u8]) -> io::Result<()> {
+    match &options.output {
+        Some(path) => File::create(path)?.write_all(out),
+        None => {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(out)?;
+            stdout.flush()
+        }
+    }
+}
+
+/// Renders the status as a `--binary` record, or as the text of `format_output`
+pub fn format_bytes(status: &Status, options: &Options) -> Result<Vec<u8>, serde_json::Error> {
+    if !options.binary {
+        return format_output(status, options).map(String::into_bytes);
+    }
+    if options.only_if_dirty && !is_dirty(status, options) {
+        return Ok(Vec::new());
+    }
+    Ok(binary::encode(status))
+}
+
+/// Renders the status as JSON, through the `--format` template or as the default line
+pub fn format_output(status: &Status, options: &Options) -> Result<String, serde_json::Error> {
+    if options.only_if_dirty && !is_dirty(status, options) {
+        return Ok(String::new());
+    }
+
+    // the counts are told apart from the booleans and strings by their json type
+    let numbers = options
+        .cap
+        .map(|_| serde_json::to_value(status))
+        .transpose()?;
+    // colors are a layer on top of the plain values, json always gets the plain ones
+    let field = |name: &str| {
+        let value = match numbers.as_ref().and_then(|numbers| numbers[name].as_i64()) {
+            Some(count) => cap_count(count, options),
+            None => status.field(name)?,
+        };
+        Some(if options.color {
+            color::paint(name, value, &options.config)
+        } else {
+            value
+        })
+    };
+
+    let out = if options.json {
+        let mut value = serde_json::to_value(status)?;
+        if options.cap_json {
+            let counts = value
+                .as_object_mut()
+                .into_iter()
+                .flat_map(|value| value.values_mut());
+            for count in counts {
+                if let Some(number) = count.as_i64() {
+                    if options.cap.is_some_and(|cap| number.unsigned_abs() > cap) {
+                        *count = Value::from(cap_count(number, options));
+                    }
+                }
+            }
+        }
+        // written by hand to keep the field order, the unset opt-in fields are left out
+        let entries = options
+            .output_fields()
+            .into_iter()
+            .filter(|name| value.get(name).is_some() || options.fields.is_some())
+            .map(|name| format!("{}:{}", Value::from(name), value[name]))
+            .collect::<Vec<_>>();
+        format!("{{{}}}", entries.join(","))
+    } else if let Some(template) = &options.format {
+        render_with(template, field)
+    } else if options.short {
+        render_short(status, options)
+    } else {
+        // git style `-z`: fields are NUL separated so the branch is passed through verbatim
+        let separator = if options.null { "\0" } else { " " };
+        // fields keep their position even when they have no value: a NUL separated one is left
+        // empty, while spaces would run together when split (`read`, `awk`), so it's a `-`
+        let empty = if options.null { "" } else { "-" };
+        options
+            .output_fields()
+            .into_iter()
+            .map(|name| {
+                field(name)
+                    .filter(|value| !value.is_empty())
+                    .unwrap_or_else(|| empty.to_string())
+            })
+            .collect::<Vec<_>>()
+            .join(separator)
+    };
+
+    // the wrapper only goes around actual content, an empty render stays empty
+    if out.is_empty() {
+        return Ok(out);
+    }
+    Ok(format!(
+        "{}{}{}",
+        options.prefix.as_deref().unwrap_or_default(),
+        out,
+        options.suffix.as_deref().unwrap_or_default()
+    ))
+}
+
+/// `--field-names`: what `format_output` prints in place of each value, the single source being
+/// `Options::output_fields`
+pub fn format_field_names(options: &Options) -> String {
+    let separator = if options.null { "\0" } else { " " };
+    options.output_fields().join(separator)
+}
+
+/// Builds a git command, running against `--directory` through git's own `-C` when set.
+///
+/// The binary can be swapped (e.g. for a wrapper) through `GITSTATUS_GIT_BIN`. The locale is
+/// forced to `C`, since the `##` line is matched against git's English messages.
+///
+/// Optional locks are off, so git status doesn't take the index lock to write back the stat
+/// info it refreshed and a prompt never gets in the way of another git command. That's
+/// `--no-optional-locks` (git 2.15), which older versions would reject, while they ignore the
+/// variable.
+fn git_command(options: &Options) -> Command {
+    let git = std::env::var_os("GITSTATUS_GIT_BIN").unwrap_or_else(|| "git".into());
+    let mut command = Command::new(git);
+    command.env("LC_ALL", "C").env("GIT_OPTIONAL_LOCKS", "0");
+    if let Some(directory) = &options.directory {
+        command.arg("-C").arg(directory);
+    }
+    command
+}
+
+/// Wait before the first `--retries` attempt, doubled on each following one
+const RETRY_DELAY: Duration = Duration::from_millis(20);
+
+/// Reads the repository through `git status --porcelain`
+fn read_porcelain(options: &Options) -> Result<Snapshot, Error> {
+    let porcelain_v2 = match options.porcelain {
+        PorcelainVersion::V1 => false,
+        PorcelainVersion::V2 => true,
+        PorcelainVersion::Auto => supports_porcelain_v2(options),
+    };
+    let porcelain_format = if porcelain_v2 {
+        "--porcelain=v2"
+    } else {
+        "--porcelain=v1"
+    };
+    let mut command = git_command(options);
+    command.args(["status", porcelain_format, "-z", "--branch"]);
+    if options.show_ignored {
+        command.arg("--ignored");
+    }
+    // only the branch header is needed, spare git the untracked files lookup, by far the
+    // slowest part on big trees
+    let entries = ENTRY_FIELDS.iter().any(|field| options.needs(field));
+    let untracked_mode = match options.untracked_mode {
+        _ if !entries => Some(UntrackedMode::No),
+        // `normal` reports an untracked directory as a single `dir/` entry, whatever
+        // `status.showUntrackedFiles` is set to
+        None if options.untracked_dirs => Some(UntrackedMode::Normal),
+        mode => mode,
+    };
+    match untracked_mode {
+        Some(UntrackedMode::Normal) => command.arg("--untracked-files=normal"),
+        Some(UntrackedMode::All) => command.arg("--untracked-files=all"),
+        Some(UntrackedMode::No) => command.arg("--untracked-files=no"),
+        None => &mut command,
+    };
+    // after ours, so a pathspec (`-- src`) can close the list
+    command.args(&options.git_args);
+
+    let mut attempt = 0;
+    let stdout = loop {
+        let porcelain = timed(options, "status", || {
+            output_with_timeout(&mut command, options.timeout)
+        });
+        match porcelain {
+            Ok(Some(porcelain)) if porcelain.status.success() => break porcelain.stdout,
+            Ok(Some(porcelain)) => match git_failure(&porcelain.stderr) {
+                // a concurrent git command is usually done within a few ms, wait a little
+                // longer on every attempt
+                Error::Locked if attempt < options.retries => {
+                    thread::sleep(RETRY_DELAY * 2u32.pow(attempt));
+                    attempt += 1;
+                }
+                error => return Err(error),
+            },
+            Ok(None) => return Err(Error::TimedOut),
+            // no git to run (not installed, `GITSTATUS_GIT_BIN` pointing nowhere): a prompt
+            // renders nothing, like outside a repository
+            Err(error) => {
+                if options.verbose {
+                    eprintln!("gitstatus: couldn't run git: {}", error);
+                }
+                return Err(Error::NotARepo);
+            }
+        }
+    };
+
+    // the `##` wording of a detached HEAD differs across git versions (`(no branch)`,
+    // `HEAD (detached at ...)`), whether HEAD is a symbolic ref doesn't. Without a branch to
+    // name, the `detached` field takes the header's word for it.
+    let detached =
+        (options.needs("branch") || options.stash_current_branch).then(|| is_detached(options));
+    let porcelain = if porcelain_v2 {
+        PorcelainVersion::V2
+    } else {
+        PorcelainVersion::V1
+    };
+    let mut snapshot = read_snapshot(stdout.as_slice(), porcelain, detached);
+    // some git versions report an unborn branch with an upstream as a plain
+    // `## main...origin/main [behind 1]`: there are no commits to be ahead or behind with
+    if (snapshot.ahead > 0 || snapshot.behind > 0) && !has_commits(options) {
+        snapshot.ahead = 0;
+        snapshot.behind = 0;
+    }
+    // naming a detached or unborn HEAD costs extra git calls
+    if options.needs("branch") {
+        if snapshot.detached {
+            snapshot.branch = get_tagname_or_hash(options);
+        } else if snapshot.branch.as_deref().is_none_or(str::is_empty) {
+            // the header wording differs across git versions on a branch without commits, HEAD
+            // itself still names the branch
+            snapshot.branch = get_symbolic_branch(options);
+        }
+    }
+
+    let on_branch = (!snapshot.detached).then_some(snapshot.branch.as_deref().unwrap_or_default());
+    Ok(Snapshot {
+        stashed: if options.needs("stashed") {
+            get_stash(options, on_branch)
+        } else {
+            0
+        },
+        detached: options.wants("detached") && snapshot.detached,
+        git_dir: (options.needs("operation") || options.needs("step"))
+            .then(|| get_git_dir(options))
+            .flatten(),
+        ..snapshot
+    })
+}
+
+/// What `git --version` says, e.g. `git version 2.43.0`
+fn git_version(options: &Options) -> Option<String> {
+    let output = timed(options, "version", || {
+        git_command(options).arg("--version").output()
+    })
+    .ok()
+    .filter(|output| output.status.success())?;
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Whether git is recent enough (2.11) for `--porcelain=v2`, from `git --version`
+fn supports_porcelain_v2(options: &Options) -> bool {
+    let Some(version) = git_version(options) else {
+        return false;
+    };
+
+    // `git version 2.43.0`, possibly followed by a vendor suffix (`(Apple Git-146)`)
+    let mut numbers = version
+        .split_whitespace()
+        .nth(2)
+        .unwrap_or_default()
+        .split('.')
+        .map(|number| number.parse::<u32>().unwrap_or(0));
+    let major = numbers.next().unwrap_or(0);
+    let minor = numbers.next().unwrap_or(0);
+    (major, minor) >= (2, 11)
+}
+
+/// Tells "outside a repository" apart from genuine errors using git's (C locale) message
+fn git_failure(stderr: &[u8]) -> Error {
+    let stderr = String::from_utf8_lossy(stderr);
+    // the bare repository check comes for free with the failing `git status`, instead of an
+    // extra `git rev-parse --is-bare-repository` on every run
+    // `-C` to a directory that doesn't exist is outside a repository as well
+    if stderr.contains("not a git repository") || stderr.contains("cannot change to") {
+        Error::NotARepo
+    } else if stderr.contains("must be run in a work tree") {
+        Error::Bare
+    } else if stderr.contains(".lock': File exists") {
+        Error::Locked
+    } else {
+        let stderr = stderr.trim();
+        Error::Git(stderr.strip_prefix("fatal: ").unwrap_or(stderr).to_string())
+    }
+}
+
+/// Runs a git invocation, reporting how long it took on stderr with `--debug`, and what git
+/// complained about when it failed with `--verbose`
+fn timed<T: Spawned>(options: &Options, name: &str, run: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = run();
+    if options.debug {
+        eprintln!(
+            "name={} ms={:.3}",
+            name,
+            start.elapsed().as_secs_f64() * 1000.0
+        );
+    }
+    if let Some(stderr) = result.stderr().filter(|_| options.verbose) {
+        let stderr = String::from_utf8_lossy(stderr);
+        // outside a repository every lookup fails the same way, and that's not an error
+        if !stderr.trim().is_empty() && !stderr.contains("not a git repository") {
+            eprintln!("gitstatus: git {} failed: {}", name, stderr.trim_end());
+        }
+    }
+    result
+}
+
+/// The outcome of a `timed` run
+trait Spawned {
+    /// What git wrote to stderr, if it exited unsuccessfully
+    fn stderr(&self) -> Option<&[u8]>;
+}
+
+impl Spawned for io::Result<Output> {
+    fn stderr(&self) -> Option<&[u8]> {
+        self.as_ref()
+            .ok()
+            .filter(|output| !output.status.success())
+            .map(|output| output.stderr.as_slice())
+    }
+}
+
+/// `output_with_timeout`, `None` when it timed out
+impl Spawned for io::Result<Option<Output>> {
+    fn stderr(&self) -> Option<&[u8]> {
+        self.as_ref()
+            .ok()
+            .and_then(Option::as_ref)
+            .filter(|output| !output.status.success())
+            .map(|output| output.stderr.as_slice())
+    }
+}
+
+/// The libgit2 backend, whose errors are already part of the result
+impl<T> Spawned for Result<T, Error> {
+    fn stderr(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+/// Runs the command like `Command::output`, except that the child is killed once the timeout
+/// expires, in which case `None` is returned
+fn output_with_timeout(
+    command: &mut Command,
+    timeout: Option<Duration>,
+) -> io::Result<Option<Output>> {
+    let Some(timeout) = timeout else {
+        return command.output().map(Some);
+    };
+
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // drain the pipes while waiting, a child blocked on a full pipe would never exit
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut buffer = vec![];
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
+            }
+            buffer
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|pipe| Box::new(pipe) as _));
+    let stderr = drain(child.stderr.take().map(|pipe| Box::new(pipe) as _));
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(5));
+    };
+
+    Ok(Some(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    }))
+}
+
+/// Glyph each nonzero count is prefixed with in `--short`, overridable through
+/// `GITSTATUS_GLYPH_<FIELD>` or the `[glyphs]` of the `--config` file
+const GLYPHS: [(&str, &str); 8] = [
+    ("ahead", "↑"),
+    ("behind", "↓"),
+    ("staged", "*"),
+    ("changed", "~"),
+    ("deleted", "-"),
+    ("untracked", "+"),
+    ("conflicts", "!"),
+    ("stashed", "$"),
+];
+
+/// `--cap`: a count beyond the cap is written as `N+`
+fn cap_count(count: i64, options: &Options) -> String {
+    match options.cap {
+        Some(cap) if count.unsigned_abs() > cap => {
+            format!("{}{}+", if count < 0 { "-" } else { "" }, cap)
+        }
+        _ => count.to_string(),
+    }
+}
+
+/// Compact `main ↑2↓1 *3 +2` segment: the branch, then every nonzero count behind its glyph,
+/// with the divergence kept together
+fn render_short(status: &Status, options: &Options) -> String {
+    let nonzero = |name: &str, count: usize| {
+        (count > 0).then(|| {
+            let glyph = options.config.glyph(name).unwrap_or_else(|| {
+                let (_, glyph) = GLYPHS.iter().find(|(field, _)| *field == name).unwrap();
+                glyph.to_string()
+            });
+            let segment = format!("{}{}", glyph, cap_count(count as i64, options));
+            if options.color {
+                color::paint(name, segment, &options.config)
+            } else {
+                segment
+            }
+        })
+    };
+
+    let divergence = [
+        nonzero("ahead", status.ahead),
+        nonzero("behind", status.behind),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<String>();
+    let counts = [
+        ("staged", status.staged),
+        ("changed", status.changed),
+        ("deleted", status.deleted),
+        ("untracked", status.untracked),
+        ("conflicts", status.conflicts),
+        ("stashed", status.stashed),
+    ]
+    .into_iter()
+    .filter_map(|(name, count)| nonzero(name, count));
+
+    (!options.no_branch)
+        .then(|| status.branch.clone())
+        .into_iter()
+        .chain((!divergence.is_empty()).then_some(divergence))
+        .chain(counts)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Names of the fields a template uses, so that only those get computed
+fn template_fields(template: &str) -> Vec<String> {
+    let mut fields = vec![];
+    render_with(template, |name| {
+        if Status::FIELDS.contains(&name) {
+            fields.push(name.to_string());
+        }
+        None
+    });
+    fields
+}
+
+/// Replaces each `{field}` token of the template with the value `lookup` gives for it.
+///
+/// Unknown tokens are kept as they are, and `{{`/`}}` produce literal braces.
+fn render_with(template: &str, mut lookup: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(idx) = rest.find(['{', '}']) {
+        out.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            out.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+
+        let token = rest
+            .find('}')
+            .map(|end| &rest[..=end])
+            .filter(|token| rest.starts_with('{') && !token[1..].contains('{'));
+
+        match token {
+            Some(token) => {
+                let name = &token[1..token.len() - 1];
+                out.push_str(&lookup(name).unwrap_or_else(|| token.to_string()));
+                rest = &rest[token.len()..];
+            }
+            None => {
+                out.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Whether none of the buckets has an entry
+pub fn is_clean(
+    changed: &[StatusLine],
+    deleted: &[StatusLine],
+    staged: &[StatusLine],
+    conflicts: &[StatusLine],
+    untracked: &[StatusLine],
+) -> bool {
+    changed.is_empty()
+        && deleted.is_empty()
+        && staged.is_empty()
+        && conflicts.is_empty()
+        && untracked.is_empty()
+}
+
+/// Whether the repository counts as dirty for `--only-if-dirty` and `--quiet`: changes, or
+/// any of the `--dirty-includes`
+pub fn is_dirty(status: &Status, options: &Options) -> bool {
+    !status.clean
+        || options.dirty_includes("ahead") && status.ahead > 0
+        || options.dirty_includes("behind") && status.behind > 0
+        || options.dirty_includes("stash") && status.stashed > 0
+}
+
+/// Counts the conflicts of one of the unmerged XY pairs of `git status --help`
+fn count_conflicts(conflicts: &[StatusLine], pair: (char, char)) -> usize {
+    conflicts
+        .iter()
+        .filter(|(x, y, _)| (*x, *y) == pair)
+        .count()
+}
+
+/// Counts the distinct extensions among the tracked entries, files without one sharing a bucket
+fn count_extensions(lines: &[StatusLine]) -> usize {
+    lines
+        .iter()
+        .filter(|(x, y, _)| !matches!((x, y), ('?', '?') | ('!', '!')))
+        .map(|(_, _, path)| {
+            Path::new(path)
+                .extension()
+                .map(|extension| extension.to_os_string())
+        })
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// Counts the entries by their `XY` pair, before their states get collapsed into buckets
+fn count_by_state(lines: &[StatusLine]) -> BTreeMap<String, usize> {
+    let mut histogram = BTreeMap::new();
+    for (x, y, _) in lines {
+        *histogram.entry(format!("{}{}", x, y)).or_default() += 1;
+    }
+    histogram
+}
+
+/// Counts the distinct top-level directories the entries live in, files at the root excluded
+fn count_top_level_dirs(lines: &[StatusLine]) -> usize {
+    lines
+        .iter()
+        .filter_map(|(_, _, path)| path.split_once('/').map(|(dir, _)| dir))
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// Absolute path of the git dir, as git resolves it (`GIT_DIR`, `-C`, worktrees, gitfiles)
+pub fn get_git_dir(options: &Options) -> Option<String> {
+    let output = timed(options, "git-dir", || {
+        git_command(options)
+            .args(["rev-parse", "--absolute-git-dir"])
+            .output()
+    })
+    .ok()
+    .filter(|output| output.status.success())?;
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Absolute path of the work tree, `None` in a bare repository or outside one
+pub fn get_toplevel(options: &Options) -> Option<String> {
+    let output = timed(options, "toplevel", || {
+        git_command(options)
+            .args(["rev-parse", "--show-toplevel"])
+            .output()
+    })
+    .ok()
+    .filter(|output| output.status.success())?;
+
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .trim_end_matches('\n')
+            .to_string(),
+    )
+}
+
+/// Counts stashes through `git stash list`, which also resolves the stash ref for worktrees
+/// and doesn't depend on the reflog file being present. With `--stash-current-branch` only the
+/// ones created on `branch` count, `None` being a detached HEAD.
+pub fn get_stash(options: &Options, branch: Option<&str>) -> usize {
+    timed(options, "stash", || {
+        git_command(options)
+            .args(["stash", "list", "--format=%gs"])
+            .output()
+    })
+    .ok()
+    .filter(|output| output.status.success())
+    .map(|output| {
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|subject| !options.stash_current_branch || is_stash_of(subject, branch))
+            .count()
+    })
+    .unwrap_or(0)
+}
+
+/// Whether the stash subject (`WIP on main: ...`, `On main: ...`) names the branch, git writes
+/// `(no branch)` for a detached HEAD. Branch names can't contain a colon.
+pub(crate) fn is_stash_of(subject: &str, branch: Option<&str>) -> bool {
+    let name = subject
+        .strip_prefix("WIP on ")
+        .or_else(|| subject.strip_prefix("On "))
+        .and_then(|rest| rest.split_once(':'))
+        .map(|(name, _)| name);
+    name == Some(branch.unwrap_or("(no branch)"))
+}
+
+/// Subject of the latest stash (`On main: wip`), `None` without stashes
+fn get_stash_subject(options: &Options) -> Option<String> {
+    let output = timed(options, "stash-subject", || {
+        git_command(options)
+            .args(["stash", "list", "-1", "--format=%gs"])
+            .output()
+    })
+    .ok()
+    .filter(|output| output.status.success())?;
+
+    let subject = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!subject.is_empty()).then_some(subject)
+}
+
+/// Counts submodules whose checked out commit differs from the recorded one (`+`) or that
+/// have merge conflicts (`U`)
+fn get_dirty_submodules(options: &Options) -> usize {
+    timed(options, "submodules", || {
+        git_command(options).args(["submodule", "status"]).output()
+    })
+    .ok()
+    .filter(|output| output.status.success())
+    .map(|output| {
+        output
+            .stdout
+            .lines()
+            .map_while(Result::ok)
+            .filter(|line| line.starts_with(['+', 'U']))
+            .count()
+    })
+    .unwrap_or(0)
+}
+
+/// Commits HEAD is ahead of and behind the target ref, `(0, 0)` when it can't be resolved
+fn get_divergence(options: &Options, target: &str) -> (usize, usize) {
+    let Some(output) = timed(options, "compare", || {
+        git_command(options)
+            .args(["rev-list", "--left-right", "--count"])
+            .arg(format!("HEAD...{}", target))
+            .output()
+    })
+    .ok()
+    .filter(|output| output.status.success()) else {
+        return (0, 0);
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut counts = stdout
+        .split_whitespace()
+        .map(|count| count.parse().unwrap_or(0));
+
+    (counts.next().unwrap_or(0), counts.next().unwrap_or(0))
+}
+
+/// Counts the commits on HEAD since it forked from the base (`base..HEAD`), 0 when the base
+/// can't be resolved. A remote's `HEAD` (`origin/HEAD`) is resolved to the branch it points at.
+fn count_since_base(options: &Options, base: &str) -> usize {
+    let base = match base.strip_suffix("/HEAD") {
+        Some(remote) => {
+            let Some(output) = timed(options, "base", || {
+                git_command(options)
+                    .args(["symbolic-ref", "-q"])
+                    .arg(format!("refs/remotes/{}/HEAD", remote))
+                    .output()
+            })
+            .ok()
+            .filter(|output| output.status.success()) else {
+                return 0;
+            };
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        }
+        None => base.to_string(),
+    };
+
+    timed(options, "since-base", || {
+        git_command(options)
+            .args(["rev-list", "--count"])
+            .arg(format!("{}..HEAD", base))
+            .output()
+    })
+    .ok()
+    .filter(|output| output.status.success())
+    .and_then(|output| String::from_utf8_lossy(&output.stdout).trim().parse().ok())
+    .unwrap_or(0)
+}
+
+/// Counts the commits on local branches that no remote-tracking branch contains, 0 when there
+/// are no remote-tracking branches at all (rather than the whole history)
+fn get_unpushed_total(options: &Options) -> usize {
+    let remotes = timed(options, "remotes", || {
+        git_command(options)
+            .args(["for-each-ref", "--count=1", "--format=x", "refs/remotes"])
+            .output()
+    });
+    match remotes {
+        Ok(remotes) if remotes.status.success() && !remotes.stdout.is_empty() => {}
+        _ => return 0,
+    }
+
+    timed(options, "unpushed", || {
+        git_command(options)
+            .args(["rev-list", "--count", "--branches", "--not", "--remotes"])
+            .output()
+    })
+    .ok()
+    .filter(|output| output.status.success())
+    .and_then(|output| String::from_utf8_lossy(&output.stdout).trim().parse().ok())
+    .unwrap_or(0)
+}
+
+/// Counts the files in the index through `git ls-files -z`
+fn count_tracked(options: &Options) -> usize {
+    timed(options, "tracked", || {
+        git_command(options).args(["ls-files", "-z"]).output()
+    })
+    .ok()
+    .filter(|output| output.status.success())
+    // every path is NUL terminated, so the last split is always the empty remainder
+    .map(|output| output.stdout.split(|&byte| byte == b'\0').count() - 1)
+    .unwrap_or(0)
+}
+
+/// Counts the configured remotes, one per line of `git remote`
+fn count_remotes(options: &Options) -> usize {
+    timed(options, "remote-count", || {
+        git_command(options).arg("remote").output()
+    })
+    .ok()
+    .filter(|output| output.status.success())
+    .map(|output| output.stdout.lines().count())
+    .unwrap_or(0)
+}
+
+/// Counts the files marked assume-unchanged and skip-worktree, whose changes git status never
+/// reports, from the tag `git ls-files -v` puts before each path: lowercase for assume-unchanged,
+/// `S` for skip-worktree (`s` for both)
+fn count_hidden(options: &Options) -> (usize, usize) {
+    let Some(output) = timed(options, "hidden", || {
+        git_command(options).args(["ls-files", "-v", "-z"]).output()
+    })
+    .ok()
+    .filter(|output| output.status.success()) else {
+        return (0, 0);
+    };
+
+    let tags = output
+        .stdout
+        .split(|&byte| byte == b'\0')
+        .filter_map(|entry| entry.first().copied());
+    tags.fold((0, 0), |(assume_unchanged, skip_worktree), tag| {
+        (
+            assume_unchanged + tag.is_ascii_lowercase() as usize,
+            skip_worktree + tag.eq_ignore_ascii_case(&b'S') as usize,
+        )
+    })
+}
+
+/// Counts the HEAD reflog entries of the last days (all of them for 0 days), 0 when there is
+/// no reflog (never written, or expired)
+fn count_reflog(options: &Options, days: u64) -> usize {
+    let Some(output) = timed(options, "reflog", || {
+        git_command(options)
+            .args([
+                "log",
+                "--walk-reflogs",
+                "--format=%gd",
+                "--date=unix",
+                "HEAD",
+            ])
+            .output()
+    })
+    .ok()
+    .filter(|output| output.status.success()) else {
+        return 0;
+    };
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let since = now.saturating_sub(days * 24 * 60 * 60);
+    // `HEAD@{<unix time of the entry>}`
+    output
+        .stdout
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|entry| {
+            entry
+                .rsplit_once('{')
+                .and_then(|(_, time)| time.trim_end_matches('}').parse::<u64>().ok())
+        })
+        .filter(|&time| days == 0 || time >= since)
+        .count()
+}
+
+/// Whether sparse checkout is enabled. `core.sparseCheckout` is what git itself goes by, a
+/// leftover `info/sparse-checkout` file after `git sparse-checkout disable` doesn't count.
+fn is_sparse(options: &Options) -> bool {
+    timed(options, "sparse", || {
+        git_command(options)
+            .args(["config", "--type=bool", "core.sparseCheckout"])
+            .output()
+    })
+    .is_ok_and(|output| output.stdout.starts_with(b"true"))
+}
+
+/// Sums the lines inserted and deleted across the staged and unstaged changes
+fn get_diffstat(options: &Options) -> (usize, usize) {
+    let mut totals = (0, 0);
+
+    for cached in [false, true] {
+        let mut command = git_command(options);
+        command.args(["diff", "--numstat"]);
+        if cached {
+            command.arg("--cached");
+        }
+        let name = if cached {
+            "diffstat-cached"
+        } else {
+            "diffstat"
+        };
+        let Some(output) = timed(options, name, || command.output())
+            .ok()
+            .filter(|output| output.status.success())
+        else {
+            continue;
+        };
+
+        // `<insertions>\t<deletions>\t<path>`, binary files report `-` for both counts
+        for line in output.stdout.lines().map_while(Result::ok) {
+            let mut counts = line.split('\t');
+            let (Some(Ok(insertions)), Some(Ok(deletions))) = (
+                counts.next().map(str::parse::<usize>),
+                counts.next().map(str::parse::<usize>),
+            ) else {
+                continue;
+            };
+            totals.0 += insertions;
+            totals.1 += deletions;
+        }
+    }
+
+    totals
+}
+
+/// Detects an in-progress operation from the state files git keeps in its dir, along with the
+/// current step (e.g. `3/7`) when the operation goes through several commits
+fn get_operation_state(git_dir: &str) -> Option<(&'static str, Option<String>)> {
+    let git_dir = Path::new(git_dir);
+    let step = |dir: &str, current: &str, total: &str| {
+        let read = |name| std::fs::read_to_string(git_dir.join(dir).join(name)).ok();
+        let (current, total) = (read(current)?, read(total)?);
+        Some(format!("{}/{}", current.trim(), total.trim()))
+    };
+
+    if git_dir.join("rebase-merge").is_dir() {
+        Some(("rebase", step("rebase-merge", "msgnum", "end")))
+    } else if git_dir.join("rebase-apply").is_dir() {
+        let operation = if git_dir.join("rebase-apply").join("applying").exists() {
+            "am"
+        } else {
+            "rebase"
+        };
+        Some((operation, step("rebase-apply", "next", "last")))
+    } else if git_dir.join("MERGE_HEAD").exists() {
+        Some(("merge", None))
+    } else if git_dir.join("CHERRY_PICK_HEAD").exists() {
+        Some(("cherry-pick", None))
+    } else if git_dir.join("REVERT_HEAD").exists() {
+        Some(("revert", None))
+    } else if git_dir.join("BISECT_LOG").exists() {
+        Some(("bisect", None))
+    } else {
+        None
+    }
+}
+
+fn get_symbolic_branch(options: &Options) -> Option<String> {
+    let output = timed(options, "symbolic-branch", || {
+        git_command(options)
+            .args(["symbolic-ref", "--short", "-q", "HEAD"])
+            .output()
+    })
+    .ok()
+    .filter(|output| output.status.success())?;
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// HEAD is detached when it isn't a symbolic ref to a branch
+fn is_detached(options: &Options) -> bool {
+    timed(options, "detached", || {
+        git_command(options)
+            .args(["symbolic-ref", "-q", "HEAD"])
+            .output()
+    })
+    .is_ok_and(|output| !output.status.success())
+}
+
+/// HEAD is unborn when it doesn't resolve to a commit
+fn has_commits(options: &Options) -> bool {
+    timed(options, "unborn", || {
+        git_command(options)
+            .args(["rev-parse", "--verify", "-q", "HEAD"])
+            .output()
+    })
+    .is_ok_and(|output| output.status.success())
+}
+
+/// Names a detached HEAD: the remote branch pointing at it (`origin/main+` when several do),
+/// else the tag, else its short hash
+pub fn get_tagname_or_hash(options: &Options) -> Option<String> {
+    points_at_remote(options)
+        .or_else(|| points_at_tag(options))
+        .or_else(|| short_head(options))
+}
+
+/// The remote-tracking branch HEAD points at, with a `+` suffix when there are several
+fn points_at_remote(options: &Options) -> Option<String> {
+    let remotes_output = timed(options, "remotes", || {
+        git_command(options)
+            .args([
+                "for-each-ref",
+                "--points-at=HEAD",
+                "--sort=refname",
+                "--format=%(refname)",
+                "refs/remotes",
+            ])
+            .output()
+    })
+    .map(|output| output.stdout)
+    .unwrap_or_default();
+
+    // `origin/HEAD` only repeats the default branch of the remote
+    let remotes = String::from_utf8_lossy(&remotes_output)
+        .lines()
+        .filter(|refname| !refname.ends_with("/HEAD"))
+        .filter_map(|refname| refname.strip_prefix("refs/remotes/"))
+        .map(|s| s.to_string())
+        .collect::<Vec<String>>();
+
+    if remotes.is_empty() {
+        return None;
+    }
+    Some(remotes[0].to_owned() + if remotes.len() > 1 { "+" } else { "" })
+}
+
+/// The tag HEAD points at, with a `+` suffix when there are several (the highest version wins)
+pub fn points_at_tag(options: &Options) -> Option<String> {
+    let tags_output = timed(options, "tags", || {
+        git_command(options)
+            .args([
+                "for-each-ref",
+                "--points-at=HEAD",
+                "--count=2",
+                "--sort=-version:refname",
+                "--format=%(refname:short)",
+                "refs/tags",
+            ])
+            .output()
+    })
+    .map(|output| output.stdout)
+    .unwrap_or_default();
+
+    let tags = String::from_utf8_lossy(&tags_output)
+        .split_whitespace()
+        .map(|s| s.to_string())
+        .collect::<Vec<String>>();
+
+    if tags.is_empty() {
+        return None;
+    }
+    Some(tags[0].to_owned() + if tags.len() > 1 { "+" } else { "" })
+}
+
+/// How long ago HEAD was committed, in git's words (`3 hours ago`), `None` on an unborn branch
+fn get_commit_age(options: &Options) -> Option<String> {
+    let output = timed(options, "commit-age", || {
+        git_command(options)
+            .args(["log", "-1", "--format=%cr"])
+            .output()
+    })
+    .ok()
+    .filter(|output| output.status.success())?;
+
+    let age = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!age.is_empty()).then_some(age)
+}
+
+/// Short hash of HEAD, `None` on an unborn branch
+fn short_head(options: &Options) -> Option<String> {
+    let hash_output = timed(options, "hash", || {
+        git_command(options)
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+    })
+    .ok()?;
+
+    let hash = String::from_utf8_lossy(&hash_output.stdout)
+        .trim()
+        .to_string();
+
+    if !hash.is_empty() {
+        Some(hash)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(porcelain: &str) -> Status {
+        parse_porcelain(porcelain.as_bytes())
+    }
+
+    /// The status of a branch with these `XY path` entries
+    fn entries(entries: &[&str]) -> Status {
+        parse(&format!("## main\0{}\0", entries.join("\0")))
+    }
+
+    #[test]
+    fn initial_commit() {
+        let status = parse("## No commits yet on main...origin/main\0A  README\0");
+        assert_eq!(status.branch, "main");
+        assert_eq!(status.upstream, "origin/main");
+        assert_eq!((status.ahead, status.behind), (0, 0));
+        assert_eq!(status.staged, 1);
+        assert!(!status.no_upstream);
+    }
+
+    #[test]
+    fn detached() {
+        let status = parse("## HEAD (no branch)\0 M src/lib.rs\0");
+        assert!(status.detached);
+        assert!(status.branch.is_empty());
+        assert!(status.upstream.is_empty());
+        assert!(!status.no_upstream);
+        assert_eq!(status.changed, 1);
+    }
+
+    #[test]
+    fn divergence() {
+        let status = parse("## main...origin/main [ahead 2, behind 3]\0");
+        assert_eq!(status.branch, "main");
+        assert_eq!(status.upstream, "origin/main");
+        assert_eq!((status.ahead, status.behind), (2, 3));
+        assert!(status.clean);
+    }
+
+    #[test]
+    fn conflicts() {
+        let status = parse("## main\0UU both\0AA added\0M  staged\0");
+        assert_eq!(status.conflicts, 2);
+        assert_eq!(status.staged, 1);
+        assert!(status.no_upstream);
+        assert!(!status.clean);
+    }
+
+    #[test]
+    fn unmerged_pairs_are_only_conflicts() {
+        let status = entries(&["AA added", "DD deleted", "UU modified"]);
+        assert_eq!(status.conflicts, 3);
+        assert_eq!(status.staged, 0);
+        assert_eq!(status.changed, 0);
+        assert_eq!(status.deleted, 0);
+        assert_eq!(status.untracked, 0);
+        assert!(!status.clean);
+    }
+
+    #[test]
+    fn staged_and_unstaged_deletions() {
+        let status = entries(&["D  staged"]);
+        assert_eq!(status.deleted_staged, Some(1));
+        assert_eq!(status.deleted_unstaged, Some(0));
+        assert_eq!((status.staged, status.deleted), (1, 0));
+
+        let status = entries(&[" D unstaged"]);
+        assert_eq!(status.deleted_staged, Some(0));
+        assert_eq!(status.deleted_unstaged, Some(1));
+        assert_eq!((status.staged, status.deleted), (0, 1));
+
+        // added, then removed from the worktree
+        let status = entries(&["AD added"]);
+        assert_eq!(status.deleted_staged, Some(0));
+        assert_eq!(status.deleted_unstaged, Some(1));
+        assert_eq!((status.staged, status.deleted), (1, 1));
+    }
+
+    #[test]
+    fn partially_staged() {
+        let status = entries(&["MM modified", "AM added", "RM renamed"]);
+        assert_eq!(status.partially_staged, Some(3));
+        // still in the staged and changed totals, once each
+        assert_eq!((status.staged, status.changed), (3, 3));
+        assert_eq!(status.renamed, 1);
+
+        let status = entries(&["M  staged", " M changed"]);
+        assert_eq!(status.partially_staged, Some(0));
+    }
+
+    #[test]
+    fn added_without_other_changes() {
+        let status = entries(&["A  new", "AM edited", "AD removed"]);
+        assert_eq!(status.added, Some(1));
+        // a superset of added
+        assert_eq!(status.staged, 3);
+    }
+
+    #[test]
+    fn unstaged_total_counts_each_file_once() {
+        let status = entries(&[" M changed", " D deleted", "MM both"]);
+        assert_eq!(status.unstaged_total, Some(3));
+        assert_eq!((status.changed, status.deleted), (2, 1));
+
+        let status = entries(&["M  staged", "?? untracked"]);
+        assert_eq!(status.unstaged_total, Some(0));
+    }
+
+    #[test]
+    fn clean_without_entries() {
+        assert!(is_clean(&[], &[], &[], &[], &[]));
+        let untracked = [('?', '?', "new".to_string())];
+        assert!(!is_clean(&[], &[], &[], &[], &untracked));
+        assert!(entries(&[]).clean);
+    }
+
+    #[test]
+    fn conflict_breakdown() {
+        let status = entries(&["DD a", "AU b", "UD c", "UA d", "DU e", "AA f", "UU g"]);
+        assert_eq!(status.conflicts, 7);
+        for count in [
+            status.both_deleted,
+            status.added_by_us,
+            status.deleted_by_them,
+            status.added_by_them,
+            status.deleted_by_us,
+            status.both_added,
+            status.both_modified,
+        ] {
+            assert_eq!(count, Some(1));
+        }
+        assert_eq!((status.staged, status.changed, status.deleted), (0, 0, 0));
+    }
+
+    #[test]
+    fn encoded_branch() {
+        let branch = || "fix/#12 two words".to_string();
+        let url = Options {
+            encode: Encoding::Url,
+            ..Default::default()
+        };
+        assert_eq!(encode_branch(branch(), &url), "fix%2F%2312%20two%20words");
+        let shell = Options {
+            encode: Encoding::Shell,
+            ..Default::default()
+        };
+        assert_eq!(encode_branch(branch(), &shell), "'fix/#12 two words'");
+        assert_eq!(encode_branch("it's".to_string(), &shell), r"'it'\''s'");
+        assert_eq!(encode_branch(branch(), &Options::default()), branch());
+    }
+
+    #[test]
+    fn operation_state_paths() {
+        let git_dir =
+            std::env::temp_dir().join(format!("gitstatus git dir {}", std::process::id()));
+        let write = |path: &[&str], content: &str| {
+            let path = path
+                .iter()
+                .fold(git_dir.clone(), |dir, part| dir.join(part));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        let state = || get_operation_state(git_dir.to_str().unwrap());
+
+        std::fs::create_dir_all(&git_dir).unwrap();
+        assert_eq!(state(), None);
+        write(&["MERGE_HEAD"], "");
+        assert_eq!(state(), Some(("merge", None)));
+        write(&["rebase-apply", "next"], "2\n");
+        write(&["rebase-apply", "last"], "3\n");
+        assert_eq!(state(), Some(("rebase", Some("2/3".to_string()))));
+        write(&["rebase-apply", "applying"], "");
+        assert_eq!(state(), Some(("am", Some("2/3".to_string()))));
+        write(&["rebase-merge", "msgnum"], "1\n");
+        write(&["rebase-merge", "end"], "4\n");
+        assert_eq!(state(), Some(("rebase", Some("1/4".to_string()))));
+
+        std::fs::remove_dir_all(&git_dir).unwrap();
+    }
+
+    #[test]
+    fn shortest_branch() {
+        let options = Options {
+            max_branch_len: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(shorten_branch("main".to_string(), &options), "…");
+        assert_eq!(shorten_branch("m".to_string(), &options), "m");
+    }
+}
@@ -0,0 +1,255 @@
+//! In-process backend built on libgit2, enabled with the `libgit2` feature
+//!
+//! The repository is opened once to read the entries, the branch, the divergence from upstream
+//! and the stashes, instead of spawning git for each of them. Only the tag/hash of a detached
+//! HEAD still goes through git, so it resolves exactly like the subprocess backend.
+
+use crate::{
+    cli::UntrackedMode, get_tagname_or_hash, is_stash_of, Error, Options, Snapshot, StatusLine,
+    ENTRY_FIELDS,
+};
+use git2::{ErrorCode, Repository, Status, StatusOptions};
+use std::collections::HashMap;
+
+pub fn read(options: &Options) -> Result<Snapshot, Error> {
+    // like git, `GIT_DIR`/`GIT_WORK_TREE` win over searching up from the current directory
+    let repo = match &options.directory {
+        Some(directory) => Repository::discover(directory),
+        None => Repository::open_from_env(),
+    };
+    let mut repo = repo.map_err(|error| match error.code() {
+        ErrorCode::NotFound => Error::NotARepo,
+        _ => Error::Git(error.message().to_string()),
+    })?;
+    if repo.is_bare() {
+        // git status refuses to run in a bare repo as well
+        return Err(Error::Bare);
+    }
+
+    let mut snapshot = Snapshot {
+        lines: if ENTRY_FIELDS.iter().any(|field| options.needs(field)) {
+            read_lines(&repo, options).map_err(|error| match error.code() {
+                ErrorCode::Locked => Error::Locked,
+                _ => Error::Git(error.message().to_string()),
+            })?
+        } else {
+            Vec::new()
+        },
+        detached: repo.head_detached().unwrap_or(false),
+        git_dir: Some(repo.path().display().to_string()),
+        ..Default::default()
+    };
+    read_branch(&repo, options, &mut snapshot);
+
+    if options.needs("stashed") {
+        let branch = (!snapshot.detached).then(|| snapshot.branch.clone().unwrap_or_default());
+        let mut stashed = 0;
+        let _ = repo.stash_foreach(|_, message, _| {
+            if !options.stash_current_branch || is_stash_of(message, branch.as_deref()) {
+                stashed += 1;
+            }
+            true
+        });
+        snapshot.stashed = stashed;
+    }
+
+    Ok(snapshot)
+}
+
+fn read_branch(repo: &Repository, options: &Options, snapshot: &mut Snapshot) {
+    if snapshot.detached {
+        if options.needs("branch") {
+            snapshot.branch = get_tagname_or_hash(options);
+        }
+        return;
+    }
+
+    let head = match repo.head() {
+        Ok(head) => head,
+        Err(error) if error.code() == ErrorCode::UnbornBranch => {
+            // same as `## No commits yet on <branch>[...<upstream>]`: the name HEAD points to,
+            // and no divergence without commits
+            let target = repo
+                .find_reference("HEAD")
+                .ok()
+                .and_then(|head| head.symbolic_target().ok().flatten().map(str::to_string));
+            snapshot.upstream = target
+                .as_deref()
+                .and_then(|target| repo.branch_upstream_name(target).ok())
+                .and_then(|upstream| upstream.as_str().ok().map(shorten));
+            snapshot.branch = target.as_deref().map(shorten);
+            return;
+        }
+        Err(_) => return,
+    };
+
+    snapshot.branch = head.shorthand().ok().map(str::to_string);
+
+    let Some(upstream) = head
+        .name()
+        .and_then(|name| repo.branch_upstream_name(name))
+        .ok()
+        .and_then(|upstream| upstream.as_str().ok().map(str::to_string))
+    else {
+        snapshot.no_upstream = true;
+        return;
+    };
+
+    // a configured upstream that no longer exists (`[gone]`) keeps the divergence at zero
+    if let (Some(local), Ok(remote)) = (head.target(), repo.refname_to_id(&upstream)) {
+        if let Ok((ahead, behind)) = repo.graph_ahead_behind(local, remote) {
+            snapshot.ahead = ahead;
+            snapshot.behind = behind;
+        }
+    }
+    snapshot.upstream = Some(shorten(&upstream));
+}
+
+/// What `status.showUntrackedFiles` asks for, which libgit2 leaves to its callers: `normal`
+/// when unset, like git
+fn untracked_mode_config(repo: &Repository) -> UntrackedMode {
+    let value = repo
+        .config()
+        .and_then(|config| config.get_string("status.showUntrackedFiles"))
+        .unwrap_or_default();
+    match value.to_lowercase().as_str() {
+        "no" | "false" | "off" | "0" => UntrackedMode::No,
+        "all" => UntrackedMode::All,
+        _ => UntrackedMode::Normal,
+    }
+}
+
+/// Strips the namespace off a full ref name, like git does in the `##` line
+fn shorten(refname: &str) -> String {
+    ["refs/heads/", "refs/remotes/"]
+        .iter()
+        .find_map(|prefix| refname.strip_prefix(prefix))
+        .unwrap_or(refname)
+        .to_string()
+}
+
+/// Lists the entries as porcelain v1 XY status lines, using git's default status settings
+fn read_lines(repo: &Repository, options: &Options) -> Result<Vec<StatusLine>, git2::Error> {
+    let untracked_mode = match options.untracked_mode {
+        Some(mode) => mode,
+        None if options.untracked_dirs => UntrackedMode::Normal,
+        None => untracked_mode_config(repo),
+    };
+    let mut status_options = StatusOptions::new();
+    status_options
+        .include_untracked(untracked_mode != UntrackedMode::No)
+        .recurse_untracked_dirs(untracked_mode == UntrackedMode::All)
+        .include_ignored(options.show_ignored)
+        .recurse_ignored_dirs(false)
+        .renames_head_to_index(true);
+
+    let statuses = repo.statuses(Some(&mut status_options))?;
+    let conflicts = read_conflicts(repo);
+
+    let lines = statuses
+        .iter()
+        .map(|entry| {
+            let path = entry.path_bytes();
+            let (x, y) = match conflicts.get(path) {
+                Some(&xy) if entry.status().is_conflicted() => xy,
+                _ => to_xy(entry.status()),
+            };
+            (x, y, String::from_utf8_lossy(path).into_owned())
+        })
+        .collect();
+
+    Ok(lines)
+}
+
+fn to_xy(status: Status) -> (char, char) {
+    if status.is_wt_new() {
+        return ('?', '?');
+    }
+    if status.is_ignored() {
+        return ('!', '!');
+    }
+    if status.is_conflicted() {
+        return ('U', 'U');
+    }
+
+    let index = if status.is_index_new() {
+        'A'
+    } else if status.is_index_modified() {
+        'M'
+    } else if status.is_index_deleted() {
+        'D'
+    } else if status.is_index_renamed() {
+        'R'
+    } else if status.is_index_typechange() {
+        'T'
+    } else {
+        ' '
+    };
+
+    let worktree = if status.is_wt_modified() {
+        'M'
+    } else if status.is_wt_deleted() {
+        'D'
+    } else if status.is_wt_typechange() {
+        'T'
+    } else if status.is_wt_renamed() {
+        'R'
+    } else {
+        ' '
+    };
+
+    (index, worktree)
+}
+
+/// Maps every conflicted path to its unmerged XY pair, derived from which stages are present
+fn read_conflicts(repo: &Repository) -> HashMap<Vec<u8>, (char, char)> {
+    let Ok(index) = repo.index() else {
+        return HashMap::new();
+    };
+    let Ok(conflicts) = index.conflicts() else {
+        return HashMap::new();
+    };
+
+    conflicts
+        .filter_map(Result::ok)
+        .filter_map(|conflict| {
+            let xy = unmerged_pair(
+                conflict.ancestor.is_some(),
+                conflict.our.is_some(),
+                conflict.their.is_some(),
+            );
+            let entry = conflict.our.or(conflict.their).or(conflict.ancestor)?;
+            Some((entry.path, xy))
+        })
+        .collect()
+}
+
+/// The XY pair `git status` shows for a conflict with these stages (base, ours, theirs) present
+fn unmerged_pair(ancestor: bool, our: bool, their: bool) -> (char, char) {
+    match (ancestor, our, their) {
+        (true, false, false) => ('D', 'D'),
+        (false, true, false) => ('A', 'U'),
+        (true, false, true) => ('D', 'U'),
+        (true, true, false) => ('U', 'D'),
+        (false, false, true) => ('U', 'A'),
+        (false, true, true) => ('A', 'A'),
+        _ => ('U', 'U'),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unmerged_pairs_from_stages() {
+        // base, ours, theirs, as in the table of `git status --help`
+        assert_eq!(unmerged_pair(true, false, false), ('D', 'D'));
+        assert_eq!(unmerged_pair(false, true, false), ('A', 'U'));
+        assert_eq!(unmerged_pair(true, false, true), ('D', 'U'));
+        assert_eq!(unmerged_pair(true, true, false), ('U', 'D'));
+        assert_eq!(unmerged_pair(false, false, true), ('U', 'A'));
+        assert_eq!(unmerged_pair(false, true, true), ('A', 'A'));
+        assert_eq!(unmerged_pair(true, true, true), ('U', 'U'));
+    }
+}
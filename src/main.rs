@@ -1,190 +1,149 @@
-use regex::Regex;
-use std::{
-    error::Error,
-    fs::File,
-    io::{self, BufRead},
-    path::Path,
-    process::Command,
-    result::Result,
+use gitstatus::{
+    branch_only_status, cache::Cache, cli, format_bytes, format_field_names, gather, get_git_dir,
+    is_dirty, render, selftest, watch, write_output, Error, Options,
 };
-
-type StatusLine = (char, char, String);
-
-fn main() -> Result<(), Box<dyn Error>> {
-    let porcelain = Command::new("git")
-        .args(["status", "--porcelain", "--branch"])
-        .output()
-        .expect("failed to wait on child");
-
-    let stdout = porcelain.stdout;
-
-    if porcelain.status.code().unwrap_or(1) != 0 {
-        // not a git repo
-        std::process::exit(0);
-    }
-
-    let mut untracked: Vec<StatusLine> = vec![];
-    let mut staged: Vec<StatusLine> = vec![];
-    let mut changed: Vec<StatusLine> = vec![];
-    let mut deleted: Vec<StatusLine> = vec![];
-    let mut conflicts: Vec<StatusLine> = vec![];
-    let mut ahead = 0;
-    let mut behind = 0;
-    let mut branch = None;
-
-    let initial_commit_re = Regex::new(r"Initial commit on").unwrap();
-    let no_commits_re = Regex::new(r"No commits yet on").unwrap();
-    let no_branch_re = Regex::new(r"no branch").unwrap();
-
-    for line in stdout.lines().map_while(Result::ok) {
-        let line = line.trim();
-        if line.len() < 3 {
-            continue;
+use std::io;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = match Options::from_env_and_args(std::env::vars(), std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("gitstatus: {}\n{}", error, cli::USAGE);
+            std::process::exit(cli::USAGE_ERROR);
         }
-        let status = (
-            line.chars().next().unwrap(),
-            line.chars().nth(1).unwrap(),
-            line[2..].to_string(),
+    };
+    if options.help {
+        write_out(&options, cli::HELP);
+        return Ok(());
+    }
+    if options.version {
+        write_out(
+            &options,
+            format!("gitstatus {}\n", env!("CARGO_PKG_VERSION")),
         );
-
-        match status {
-            ('#', '#', ref git_ref) => {
-                if initial_commit_re.is_match(git_ref) || no_commits_re.is_match(git_ref) {
-                    branch = Some(
-                        status
-                            .2
-                            .split_whitespace()
-                            .last()
-                            .unwrap_or_default()
-                            .to_string(),
-                    );
-                } else if no_branch_re.is_match(git_ref) {
-                    branch = get_tagname_or_hash();
-                } else if git_ref.trim().split("...").count() == 1 {
-                    branch = Some(git_ref.trim().to_string());
-                } else {
-                    let parts: Vec<&str> = git_ref.trim().split("...").collect();
-                    branch = Some(parts[0].to_string());
-                    let rest = parts[1];
-                    if rest.split_whitespace().count() > 1 {
-                        let divergence = rest
-                            .split_whitespace()
-                            .skip(1)
-                            .collect::<Vec<&str>>()
-                            .join(" ");
-                        let divergence = divergence.trim_start_matches('[').trim_end_matches(']');
-                        for div in divergence.split(", ") {
-                            if div.contains("ahead") {
-                                ahead = div["ahead ".len()..].trim().parse().unwrap_or(0);
-                            } else if div.contains("behind") {
-                                behind = div["behind ".len()..].trim().parse().unwrap_or(0);
-                            }
-                        }
-                    }
-                }
-            }
-            ('?', '?', _) => untracked.push(status),
-            (_, 'M', _) => changed.push(status),
-            (_, 'D', _) => deleted.push(status),
-            ('U', _, _) => conflicts.push(status),
-            (c, _, _) if c != ' ' => staged.push(status),
-            _ => {}
-        }
+        return Ok(());
     }
 
-    let stashed = get_stash();
-    let clean = is_clean(&changed, &deleted, &staged, &conflicts, &untracked);
-
-    let out = format!(
-        "{} {} {} {} {} {} {} {} {} {}",
-        branch.unwrap_or_default(),
-        ahead,
-        behind,
-        staged.len(),
-        conflicts.len(),
-        changed.len(),
-        untracked.len(),
-        stashed,
-        clean,
-        deleted.len()
-    );
-    print!("{}", out);
-
-    Ok(())
-}
-
-fn is_clean(
-    changed: &[StatusLine],
-    deleted: &[StatusLine],
-    staged: &[StatusLine],
-    conflicts: &[StatusLine],
-    untracked: &[StatusLine],
-) -> i32 {
-    if changed.is_empty()
-        && deleted.is_empty()
-        && staged.is_empty()
-        && conflicts.is_empty()
-        && untracked.is_empty()
-    {
-        1
-    } else {
-        0
+    if options.field_names {
+        write_out(&options, format_field_names(&options));
+        return Ok(());
     }
-}
-
-fn get_stash() -> usize {
-    let output = Command::new("git")
-        .args(["rev-parse", "--git-dir"])
-        .output()
-        .expect("failed to execute command");
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stash_file = format!("{}/logs/refs/stash", stdout.trim());
+    if options.selftest {
+        write_out(&options, selftest::report(&options));
+        return Ok(());
+    }
 
-    if let Ok(file) = File::open(Path::new(&stash_file)) {
-        let reader = io::BufReader::new(file);
-        reader.lines().count()
-    } else {
-        0
+    if options.quiet {
+        let code = match gather(&options) {
+            Ok(status) if options.fail_on_conflict && status.conflicts > 0 => cli::CONFLICTED,
+            Ok(status) if is_dirty(&status, &options) => cli::DIRTY,
+            Ok(_) | Err(Error::Bare) => 0,
+            // nothing tells it's clean
+            Err(Error::TimedOut | Error::Locked) => cli::DIRTY,
+            Err(Error::NotARepo) => cli::NOT_A_REPO,
+            Err(Error::Git(error)) => {
+                eprintln!("gitstatus: {}", error);
+                cli::GIT_ERROR
+            }
+        };
+        std::process::exit(code);
     }
-}
 
-fn get_tagname_or_hash() -> Option<String> {
-    // Get the tag name
-    let tags_output = Command::new("git")
-        .args([
-            "for-each-ref",
-            "--points-at=HEAD",
-            "--count=2",
-            "--sort=-version:refname",
-            "--format=%(refname:short)",
-            "refs/tags",
-        ])
-        .output()
-        .expect("failed to execute command");
+    if let Some(repos) = &options.repos {
+        // one process for all of them, each read in isolation and never cached. With -z the
+        // fields are NUL separated, empty ones included, so a record still ends with a newline
+        let (separator, terminator) = if options.null {
+            ("\0", "\n")
+        } else {
+            (" ", "\n")
+        };
+        let mut out = String::new();
+        for repo in repos {
+            let repo_options = Options {
+                directory: Some(repo.clone()),
+                ..options.clone()
+            };
+            let record = render(&repo_options)?.unwrap_or_default();
+            let record = String::from_utf8_lossy(&record);
+            out.push_str(&format!("{}{}{}{}", repo, separator, record, terminator));
+        }
+        write_out(&options, &out);
+        return Ok(());
+    }
 
-    let tags = String::from_utf8_lossy(&tags_output.stdout)
-        .split_whitespace()
-        .map(|s| s.to_string())
-        .collect::<Vec<String>>();
+    if options.watch {
+        return watch::run(&options);
+    }
 
-    if !tags.is_empty() {
-        return Some(tags[0].to_owned() + if tags.len() > 1 { "+" } else { "" });
+    // `--no-cache` skips the lookup but still refreshes the entry, and `--binary` records are
+    // never cached as they aren't text
+    let cache = options.cache_dir.as_deref().filter(|_| !options.binary);
+    let cache = cache.and_then(|cache_dir| {
+        let git_dir = get_git_dir(&options)?;
+        let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+        // `--color auto` renders differently depending on where stdout goes, and the defaults
+        // from the environment (glyphs and colors included) count as much as the arguments
+        args.push(format!("color={}", options.color));
+        let mut env = std::env::vars()
+            .filter(|(name, _)| name.starts_with("GITSTATUS_"))
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>();
+        env.sort();
+        args.extend(env);
+        Cache::new(cache_dir, &git_dir, &args)
+    });
+    // a cached render doesn't tell whether there are conflicts
+    if let Some(out) = cache
+        .as_ref()
+        .filter(|_| !options.no_cache && !options.fail_on_conflict)
+        .and_then(Cache::load)
+    {
+        write_out(&options, &out);
+        return Ok(());
     }
 
-    // Get the hash
-    let hash_output = Command::new("git")
-        .args(["rev-parse", "--short", "HEAD"])
-        .output()
-        .expect("failed to execute command");
+    let mut conflicted = false;
+    let out = match gather(&options) {
+        Ok(status) => {
+            conflicted = status.conflicts > 0;
+            let out = format_bytes(&status, &options)?;
+            if let Some(cache) = &cache {
+                cache.store(&String::from_utf8_lossy(&out));
+            }
+            out
+        }
+        // the degraded output isn't cached, the next run should get the real counts
+        Err(error @ (Error::TimedOut | Error::Bare | Error::Locked)) => {
+            format_bytes(&branch_only_status(&options, &error), &options)?
+        }
+        Err(Error::NotARepo) => {
+            // render nothing, but json consumers still get a valid (empty) object, and an
+            // `--output` file doesn't keep the status of the last repository
+            write_out(&options, if options.json { "{}" } else { "" });
+            std::process::exit(0);
+        }
+        Err(Error::Git(error)) => {
+            eprintln!("gitstatus: {}", error);
+            std::process::exit(cli::GIT_ERROR);
+        }
+    };
+    write_out(&options, &out);
+    if options.fail_on_conflict && conflicted {
+        std::process::exit(cli::CONFLICTED);
+    }
 
-    let hash = String::from_utf8_lossy(&hash_output.stdout)
-        .trim()
-        .to_string();
+    Ok(())
+}
 
-    if !hash.is_empty() {
-        Some(hash)
-    } else {
-        None
+/// Writes the output, treating a reader that went away early (e.g. `| head`) as success
+fn write_out(options: &Options, out: impl AsRef<[u8]>) {
+    match write_output(options, out.as_ref()) {
+        Err(error) if error.kind() != io::ErrorKind::BrokenPipe => {
+            let sink = options.output.as_deref().unwrap_or("stdout");
+            eprintln!("gitstatus: couldn't write to {}: {}", sink, error);
+            std::process::exit(cli::OUTPUT_ERROR);
+        }
+        _ => {}
     }
 }
@@ -0,0 +1,240 @@
+//! Parser for `git status --porcelain=v1 --branch -z`
+//!
+//! Every entry is an `XY path` record, and the branch details come as a single
+//! `## branch...upstream [ahead N, behind M]` header whose wording depends on the state of HEAD.
+//! `git status -sb` writes the same records, though possibly colored (`color.status=always`).
+
+use crate::{Branch, StatusLine};
+use regex::Regex;
+use std::sync::LazyLock;
+
+// compiled once, `--watch` parses a header on every refresh
+static INITIAL_COMMIT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"Initial commit on").unwrap());
+static NO_COMMITS_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"No commits yet on").unwrap());
+static NO_BRANCH_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"no branch").unwrap());
+static COLOR_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap());
+
+/// Parses the v1 records into the branch details and one `StatusLine` per entry.
+///
+/// Output captured without `-z` (one entry per line) is accepted as well, which is what
+/// `--stdin` usually gets, `git status -sb` included. Its entries end up the same as with `-z`:
+/// uncolored, and a rename written `orig -> path` keeps only the path.
+pub fn parse(stdout: &[u8]) -> (Branch, Vec<StatusLine>) {
+    let mut branch = Branch::default();
+    let mut lines = vec![];
+
+    // with `-z` every record is NUL terminated and paths are never quoted, so
+    // names containing spaces or newlines are kept as a single record
+    let null = stdout.contains(&b'\0');
+    let separator = if null { b'\0' } else { b'\n' };
+    let mut records = stdout
+        .split(|&byte| byte == separator)
+        .map(String::from_utf8_lossy);
+
+    while let Some(record) = records.next() {
+        // git on Windows ends the lines with `\r\n`, while a `-z` path is kept verbatim
+        let record = if null {
+            record
+        } else {
+            let record = record.strip_suffix('\r').unwrap_or(&record);
+            COLOR_RE.replace_all(record, "").into_owned().into()
+        };
+        let mut chars = record.chars();
+        let (Some(x), Some(y)) = (chars.next(), chars.next()) else {
+            continue;
+        };
+        let path = chars.as_str();
+        let path = path.strip_prefix(' ').unwrap_or(path);
+        if path.is_empty() {
+            continue;
+        }
+
+        // renames and copies are followed by an extra record holding the original path, or use
+        // `orig -> path` on their own line without `-z`
+        let renamed = x == 'R' || x == 'C' || y == 'R' || y == 'C';
+        let path = if !renamed {
+            path
+        } else if null {
+            records.next();
+            path
+        } else {
+            path.rsplit_once(" -> ").map_or(path, |(_, path)| path)
+        };
+
+        match (x, y) {
+            ('#', '#') => parse_header(path, &mut branch),
+            _ => lines.push((x, y, path.to_string())),
+        }
+    }
+
+    (branch, lines)
+}
+
+fn parse_header(git_ref: &str, branch: &mut Branch) {
+    // an unborn branch can still have an upstream: `No commits yet on main...origin/main`
+    let mut git_ref = git_ref;
+    if let Some(found) = INITIAL_COMMIT_RE
+        .find(git_ref)
+        .or_else(|| NO_COMMITS_RE.find(git_ref))
+    {
+        branch.initial = true;
+        git_ref = &git_ref[found.end()..];
+    }
+
+    if NO_BRANCH_RE.is_match(git_ref) {
+        branch.detached = true;
+    } else if git_ref.trim().split("...").count() == 1 {
+        branch.head = Some(strip_heads(git_ref.trim()));
+    } else {
+        let parts: Vec<&str> = git_ref.trim().split("...").collect();
+        branch.head = Some(strip_heads(parts[0]));
+        let rest = parts[1];
+        branch.upstream = rest.split_whitespace().next().map(str::to_string);
+        if rest.split_whitespace().count() > 1 {
+            let divergence = rest
+                .split_whitespace()
+                .skip(1)
+                .collect::<Vec<&str>>()
+                .join(" ");
+            let divergence = divergence.trim_start_matches('[').trim_end_matches(']');
+            // `[ahead N, behind M]`: either side may be missing, and the order isn't relied upon
+            for div in divergence.split(", ") {
+                let count = trailing_count(div);
+                if div.contains("ahead") {
+                    branch.ahead = count;
+                } else if div.contains("behind") {
+                    branch.behind = count;
+                }
+            }
+        }
+    }
+
+    // there are no commits to be ahead or behind with
+    if branch.initial {
+        branch.ahead = 0;
+        branch.behind = 0;
+    }
+}
+
+/// The last number of a divergence token, whatever comes around it (`0` when there's none), so
+/// an odd token is never sliced at an assumed prefix length
+fn trailing_count(token: &str) -> usize {
+    token
+        .trim_end_matches(|c: char| !c.is_ascii_digit())
+        .rsplit(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Drops the `refs/heads/` or `heads/` some worktree setups leave on the `##` branch
+fn strip_heads(branch: &str) -> String {
+    ["refs/heads/", "heads/"]
+        .iter()
+        .find_map(|prefix| branch.strip_prefix(prefix))
+        .unwrap_or(branch)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(git_ref: &str) -> Branch {
+        let mut branch = Branch::default();
+        parse_header(git_ref, &mut branch);
+        branch
+    }
+
+    #[test]
+    fn divergence_in_any_order() {
+        let branch = header("main...origin/main [behind 3, ahead 1]");
+        assert_eq!((branch.ahead, branch.behind), (1, 3));
+        assert_eq!(branch.upstream.as_deref(), Some("origin/main"));
+    }
+
+    #[test]
+    fn divergence_ahead_only() {
+        let branch = header("main...origin/main [ahead 2]");
+        assert_eq!((branch.ahead, branch.behind), (2, 0));
+    }
+
+    #[test]
+    fn divergence_behind_only() {
+        let branch = header("main...origin/main [behind 5]");
+        assert_eq!((branch.ahead, branch.behind), (0, 5));
+    }
+
+    #[test]
+    fn malformed_divergence_tokens() {
+        assert_eq!(trailing_count("xahead"), 0);
+        assert_eq!(trailing_count("ahead"), 0);
+        assert_eq!(trailing_count("behind 3]"), 3);
+        assert_eq!(trailing_count("ahead ✓"), 0);
+
+        let branch = header("main...origin/main [xahead, behindé 4, ahead]");
+        assert_eq!((branch.ahead, branch.behind), (0, 4));
+    }
+
+    #[test]
+    fn heads_prefix_is_stripped() {
+        let (branch, _) = parse(b"## heads/main...origin/main\0");
+        assert_eq!(branch.head.as_deref(), Some("main"));
+        assert_eq!(branch.upstream.as_deref(), Some("origin/main"));
+
+        let (branch, _) = parse(b"## refs/heads/feature/x\0");
+        assert_eq!(branch.head.as_deref(), Some("feature/x"));
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let (branch, lines) =
+            parse(b"## main...origin/main [ahead 1]\r\n M src/lib.rs\r\n?? new\r\n");
+        assert_eq!(branch.head.as_deref(), Some("main"));
+        assert_eq!(branch.upstream.as_deref(), Some("origin/main"));
+        assert_eq!(branch.ahead, 1);
+        assert_eq!(
+            lines,
+            [
+                (' ', 'M', "src/lib.rs".to_string()),
+                ('?', '?', "new".to_string())
+            ]
+        );
+
+        let (branch, _) = parse(b"## main\r\n");
+        assert_eq!(branch.head.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn short_and_porcelain_read_the_same() {
+        // `git status -sb` with `color.status=always`
+        let short = b"## \x1b[32mmain\x1b[m...\x1b[31morigin/main\x1b[m [ahead \x1b[32m1\x1b[m]\n\
+            \x1b[32mR\x1b[m  old name -> new name\n \x1b[31mM\x1b[m src/lib.rs\n\
+            \x1b[31m??\x1b[m new\n";
+        let porcelain =
+            b"## main...origin/main [ahead 1]\nR  old name -> new name\n M src/lib.rs\n?? new\n";
+        let porcelain_z =
+            b"## main...origin/main [ahead 1]\0R  new name\0old name\0 M src/lib.rs\0?? new\0";
+
+        let expected = [
+            ('R', ' ', "new name".to_string()),
+            (' ', 'M', "src/lib.rs".to_string()),
+            ('?', '?', "new".to_string()),
+        ];
+        for input in [&short[..], porcelain, porcelain_z] {
+            let (branch, lines) = parse(input);
+            assert_eq!(branch.head.as_deref(), Some("main"));
+            assert_eq!(branch.upstream.as_deref(), Some("origin/main"));
+            assert_eq!((branch.ahead, branch.behind), (1, 0));
+            assert_eq!(lines, expected);
+        }
+    }
+
+    #[test]
+    fn null_separated_path_with_a_newline() {
+        let (branch, lines) = parse(b"## main\0 M foo\nbar\0");
+        assert_eq!(branch.head.as_deref(), Some("main"));
+        assert_eq!(lines, [(' ', 'M', "foo\nbar".to_string())]);
+    }
+}
@@ -0,0 +1,142 @@
+//! Parser for `git status --porcelain=v2 --branch -z`
+//!
+//! Unlike v1, every v2 entry starts with a type tag (`1`, `2`, `u`, `?`, `!`) and carries the XY
+//! status as a dedicated field, while the branch details come as `# branch.*` headers.
+
+use crate::{Branch, StatusLine};
+
+/// Parses the v2 records into the branch details and one `StatusLine` per entry.
+///
+/// The `.` v2 uses for an unmodified column is mapped to a space so entries classify the same
+/// way as v1 ones. As with v1, output captured without `-z` (one entry per line, what `--stdin`
+/// usually gets) is accepted as well.
+pub fn parse(stdout: &[u8]) -> (Branch, Vec<StatusLine>) {
+    let mut branch = Branch::default();
+    let mut lines = vec![];
+
+    let null = stdout.contains(&b'\0');
+    let separator = if null { b'\0' } else { b'\n' };
+    // git on Windows ends the lines with `\r\n`, while a `-z` path is kept verbatim
+    let mut records = stdout
+        .split(|&byte| byte == separator)
+        .map(|record| match record.strip_suffix(b"\r") {
+            Some(line) if !null => line,
+            _ => record,
+        })
+        .map(String::from_utf8_lossy);
+
+    while let Some(record) = records.next() {
+        let mut fields = record.splitn(2, ' ');
+        let (Some(tag), Some(rest)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+
+        match tag {
+            "#" => parse_header(rest, &mut branch),
+            "?" => lines.push(('?', '?', rest.to_string())),
+            "!" => lines.push(('!', '!', rest.to_string())),
+            // `1 XY sub mH mI mW hH hI path`
+            "1" => lines.extend(parse_entry(rest, 6)),
+            // `2 XY sub mH mI mW hH hI Xscore path`, followed by the original path record, or
+            // by a tab and the original path on the same line without `-z`
+            "2" if null => {
+                lines.extend(parse_entry(rest, 7));
+                records.next();
+            }
+            "2" => lines
+                .extend(parse_entry(rest, 7).map(|(x, y, path)| {
+                    (x, y, path.split('\t').next().unwrap_or_default().into())
+                })),
+            // `u XY sub m1 m2 m3 mW h1 h2 h3 path`
+            "u" => lines.extend(parse_entry(rest, 8)),
+            _ => {}
+        }
+    }
+
+    (branch, lines)
+}
+
+fn parse_header(header: &str, branch: &mut Branch) {
+    let Some((key, value)) = header.split_once(' ') else {
+        return;
+    };
+
+    match key {
+        "branch.oid" => branch.initial = value == "(initial)",
+        "branch.head" if value == "(detached)" => branch.detached = true,
+        "branch.head" => branch.head = Some(value.to_string()),
+        "branch.upstream" => branch.upstream = Some(value.to_string()),
+        "branch.ab" => {
+            for count in value.split_whitespace() {
+                if let Some(ahead) = count.strip_prefix('+') {
+                    branch.ahead = ahead.parse().unwrap_or(0);
+                } else if let Some(behind) = count.strip_prefix('-') {
+                    branch.behind = behind.parse().unwrap_or(0);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Reads the XY field and the path, skipping the `skip` fields in between
+fn parse_entry(entry: &str, skip: usize) -> Option<StatusLine> {
+    let mut fields = entry.splitn(skip + 2, ' ');
+    let mut xy = fields
+        .next()?
+        .chars()
+        .map(|c| if c == '.' { ' ' } else { c });
+    let (x, y) = (xy.next()?, xy.next()?);
+    let path = fields.nth(skip)?;
+
+    Some((x, y, path.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADERS: &str = "# branch.oid 1234567\n# branch.head main\n\
+        # branch.upstream origin/main\n# branch.ab +1 -2\n";
+    const ENTRIES: [&str; 3] = [
+        "1 .M N... 100644 100644 100644 1111111 1111111 changed file",
+        "2 R. N... 100644 100644 100644 2222222 2222222 R100 new name",
+        "? untracked",
+    ];
+
+    fn assert_parsed((branch, lines): (Branch, Vec<StatusLine>)) {
+        assert_eq!(branch.head.as_deref(), Some("main"));
+        assert_eq!(branch.upstream.as_deref(), Some("origin/main"));
+        assert_eq!((branch.ahead, branch.behind), (1, 2));
+        assert_eq!(
+            lines,
+            [
+                (' ', 'M', "changed file".to_string()),
+                ('R', ' ', "new name".to_string()),
+                ('?', '?', "untracked".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn null_separated() {
+        let stdout = format!(
+            "{}{}\0{}\0old name\0{}\0",
+            HEADERS.replace('\n', "\0"),
+            ENTRIES[0],
+            ENTRIES[1],
+            ENTRIES[2]
+        );
+        assert_parsed(parse(stdout.as_bytes()));
+    }
+
+    #[test]
+    fn newline_separated() {
+        let stdout = format!(
+            "{}{}\n{}\told name\n{}\n",
+            HEADERS, ENTRIES[0], ENTRIES[1], ENTRIES[2]
+        );
+        assert_parsed(parse(stdout.as_bytes()));
+        assert_parsed(parse(stdout.replace('\n', "\r\n").as_bytes()));
+    }
+}
@@ -0,0 +1,73 @@
+//! `--selftest`: a human readable report of what gitstatus sees from here
+//!
+//! Meant for "why is my prompt empty" questions: it tells which git runs, whether a repository
+//! was found and what every field ends up as, without reading `--debug` timings.
+
+use crate::{gather, get_git_dir, get_toplevel, git_version, Error, Options};
+use std::{fmt::Write, time::Instant};
+
+/// Runs the same lookups as a normal run and describes them, nothing in here fails
+pub fn report(options: &Options) -> String {
+    let mut report = String::new();
+    let git = std::env::var("GITSTATUS_GIT_BIN").unwrap_or_else(|_| "git".to_string());
+    let directory = match &options.directory {
+        Some(directory) => directory.clone(),
+        None => std::env::current_dir()
+            .map(|directory| directory.display().to_string())
+            .unwrap_or_default(),
+    };
+
+    let _ = writeln!(report, "gitstatus {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(
+        report,
+        "git:        {}",
+        match git_version(options) {
+            Some(version) => format!("{} ({})", version, git),
+            None => format!("couldn't run '{}'", git),
+        }
+    );
+    let _ = writeln!(report, "directory:  {}", directory);
+    let _ = writeln!(
+        report,
+        "git dir:    {}",
+        get_git_dir(options).unwrap_or_else(|| "none, not a repository".to_string())
+    );
+    let _ = writeln!(
+        report,
+        "work tree:  {}",
+        get_toplevel(options).unwrap_or_else(|| "none".to_string())
+    );
+
+    let start = Instant::now();
+    let status = gather(options);
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    let status = match status {
+        Ok(status) => {
+            let _ = writeln!(report, "status:     read in {:.1} ms", elapsed);
+            status
+        }
+        Err(error) => {
+            let outcome = match error {
+                Error::NotARepo => "nothing is printed outside a repository",
+                Error::Bare | Error::TimedOut | Error::Locked => "only the branch is printed",
+                Error::Git(_) => "nothing is printed, exit status 2",
+            };
+            let _ = writeln!(
+                report,
+                "status:     {} after {:.1} ms, {}",
+                error, elapsed, outcome
+            );
+            return report;
+        }
+    };
+
+    // the fields a normal run with these options prints, quoted so empty ones stand out
+    report.push('\n');
+    let fields = options.output_fields();
+    let width = fields.iter().map(|name| name.len()).max().unwrap_or(0);
+    for name in fields {
+        let value = status.field(name).unwrap_or_default();
+        let _ = writeln!(report, "  {:width$}  {:?}", name, value, width = width);
+    }
+    report
+}
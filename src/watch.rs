@@ -0,0 +1,85 @@
+//! `--watch`: stay alive and print the status again whenever the repository changes
+//!
+//! The work tree and the git dir are watched through `notify`. Bursts of events (a checkout,
+//! a build writing hundreds of files) are debounced, and a line is only printed when it differs
+//! from the previous one, so git refreshing the index while we read it doesn't loop.
+//!
+//! Records are NUL terminated, except with `--json`: JSON lines, one object per refresh with
+//! the same fields as a single `--json` run, since an object never contains a raw newline.
+//! `--binary` records carry their own length and are written back to back.
+
+use crate::{get_git_dir, get_toplevel, render, write_output, Options};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::{fs, io, path::PathBuf, sync::mpsc, time::Duration};
+
+/// Quiet period after the last event before the status is read again
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+pub fn run(options: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+
+    let Some(toplevel) = get_toplevel(options).map(PathBuf::from) else {
+        // nothing to watch outside a repository: print the (empty) status once like a normal run
+        emit(options, &render(options)?.unwrap_or_default())?;
+        return Ok(());
+    };
+    watcher.watch(&toplevel, RecursiveMode::Recursive)?;
+    // linked worktrees and `--separate-git-dir` keep HEAD and the index outside the work tree
+    if let Some(git_dir) = get_git_dir(options).and_then(|git_dir| fs::canonicalize(git_dir).ok()) {
+        if !git_dir.starts_with(&toplevel) {
+            watcher.watch(&git_dir, RecursiveMode::NonRecursive)?;
+        }
+    }
+
+    let mut last = None;
+    loop {
+        // a failed read (e.g. the index stayed locked) is retried on the next event anyway
+        if let Some(out) = render(options)? {
+            if last.as_ref() != Some(&out) {
+                if !emit(options, &out)? {
+                    return Ok(());
+                }
+                last = Some(out);
+            }
+        }
+
+        // block until something relevant happens, then wait for the burst to settle
+        loop {
+            match events.recv() {
+                Ok(Ok(event)) if is_relevant(&event) => break,
+                Ok(_) => continue,
+                // the watcher is gone, nothing will ever change again
+                Err(_) => return Ok(()),
+            }
+        }
+        while events.recv_timeout(DEBOUNCE).is_ok() {}
+    }
+}
+
+/// Writes one record and flushes it, `false` once the reader went away. With `--output` the
+/// file is rewritten every time, so it always holds the latest record.
+fn emit(options: &Options, out: &[u8]) -> io::Result<bool> {
+    let terminator: &[u8] = if options.binary {
+        b""
+    } else if options.json {
+        b"\n"
+    } else {
+        b"\0"
+    };
+    let record = [out, terminator].concat();
+    match write_output(options, &record) {
+        Ok(()) => Ok(true),
+        Err(error) if error.kind() == io::ErrorKind::BrokenPipe => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Reads don't change anything, and lock files come and go around every write git makes
+fn is_relevant(event: &Event) -> bool {
+    !matches!(event.kind, EventKind::Access(_))
+        && event
+            .paths
+            .iter()
+            .any(|path| path.extension().is_none_or(|extension| extension != "lock"))
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::Repo;
+
+#[test]
+fn no_upstream_keeps_the_fields_in_place() {
+    let repo = Repo::with_commit();
+    let fields = repo.fields(&[]);
+    assert_eq!(fields["upstream"], "-");
+    assert_eq!(fields["no_upstream"], "1");
+}
+
+#[test]
+fn upstream_of_a_clone() {
+    let origin = Repo::with_commit();
+    let repo = Repo::clone_of(&origin, &[]);
+    let fields = repo.fields(&[]);
+    assert_eq!(fields["branch"], "main");
+    assert_eq!(fields["upstream"], "origin/main");
+    assert_eq!(fields["no_upstream"], "0");
+}
+
+#[test]
+fn detached_checkout() {
+    let repo = Repo::with_commit();
+    let hash = repo.git(&["rev-parse", "--short", "HEAD"]);
+    repo.git(&["checkout", "-q", "--detach"]);
+    assert_eq!(
+        repo.stdout(&["--format", "{branch} {detached}"]),
+        format!("{} 1", hash.trim())
+    );
+}
+
+#[test]
+fn normal_branch_isnt_detached() {
+    let repo = Repo::with_commit();
+    assert_eq!(repo.stdout(&["--format", "{branch} {detached}"]), "main 0");
+}
+
+#[test]
+fn branch_on_a_tag() {
+    let repo = Repo::with_commit();
+    assert_eq!(
+        repo.stdout(&["--with-tag", "--format", "{branch}|{tag}"]),
+        "main|"
+    );
+    repo.git(&["tag", "v1.0"]);
+    assert_eq!(
+        repo.stdout(&["--with-tag", "--format", "{branch}|{tag}"]),
+        "main|v1.0"
+    );
+}
+
+#[test]
+fn freshly_initialized_repository() {
+    let repo = Repo::dir();
+    repo.git(&["-c", "init.defaultBranch=main", "init", "-q"]);
+    for porcelain in ["1", "2"] {
+        let fields = repo.fields(&["--porcelain-version", porcelain]);
+        assert_eq!(fields["branch"], "main");
+        assert_eq!(fields["clean"], "1");
+        assert_eq!(fields["no_upstream"], "0");
+    }
+}
+
+#[test]
+fn checked_out_remote_ref() {
+    let origin = Repo::with_commit();
+    origin.git(&["checkout", "-q", "-b", "feature"]);
+    origin.commit("feature");
+    let repo = Repo::clone_of(&origin, &[]);
+    repo.git(&["checkout", "-q", "origin/feature"]);
+    assert_eq!(
+        repo.stdout(&["--format", "{branch} {detached}"]),
+        "origin/feature 1"
+    );
+
+    // with several remote branches at HEAD, the first one is named with a `+`
+    repo.git(&["checkout", "-q", "origin/main"]);
+    repo.git(&["push", "-q", "origin", "HEAD:refs/heads/release"]);
+    repo.git(&["fetch", "-q"]);
+    assert_eq!(repo.stdout(&["--format", "{branch}"]), "origin/main+");
+}
@@ -0,0 +1,199 @@
+//! Throwaway repositories for the integration tests, built with the git on PATH and removed
+//! when dropped. Neither the user's git config nor their `GITSTATUS_*` defaults leak in.
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{self, Command, Output, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Repo {
+    pub path: PathBuf,
+}
+
+impl Repo {
+    /// An empty directory, not a repository yet
+    pub fn dir() -> Repo {
+        let path = env::temp_dir().join(format!(
+            "gitstatus-test-{}-{}",
+            process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Repo { path }
+    }
+
+    /// A repository on an unborn `main`, whatever `init.defaultBranch` the git has
+    pub fn new() -> Repo {
+        let repo = Repo::dir();
+        repo.git(&["init", "-q"]);
+        repo.git(&["symbolic-ref", "HEAD", "refs/heads/main"]);
+        repo
+    }
+
+    /// A repository with a first commit on `main`
+    pub fn with_commit() -> Repo {
+        let repo = Repo::new();
+        repo.write("README", "readme\n");
+        repo.commit("initial");
+        repo
+    }
+
+    /// A clone of `origin`, extra `git clone` arguments (`--depth 1`) included
+    pub fn clone_of(origin: &Repo, args: &[&str]) -> Repo {
+        let repo = Repo::dir();
+        let url = format!("file://{}", origin.path.display());
+        repo.git(&[&["clone", "-q"], args, &[url.as_str(), "."]].concat());
+        repo
+    }
+
+    /// Runs git in the repository, panicking when it fails, and returns its stdout
+    pub fn git(&self, args: &[&str]) -> String {
+        let output = git_env(Command::new("git"))
+            .args(args)
+            .current_dir(&self.path)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    /// Like `git`, for the commands expected to fail (a conflicting merge or rebase)
+    pub fn git_failing(&self, args: &[&str]) {
+        let output = git_env(Command::new("git"))
+            .args(args)
+            .current_dir(&self.path)
+            .output()
+            .unwrap();
+        assert!(!output.status.success(), "git {:?} didn't fail", args);
+    }
+
+    pub fn write(&self, path: &str, content: &str) {
+        let path = self.path.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, content).unwrap();
+    }
+
+    pub fn remove(&self, path: &str) {
+        fs::remove_file(self.path.join(path)).unwrap();
+    }
+
+    /// Stages everything and commits it
+    pub fn commit(&self, message: &str) {
+        self.git(&["add", "-A"]);
+        self.git(&["commit", "-q", "--allow-empty", "-m", message]);
+    }
+
+    /// An executable shell script in the repository directory, for `GITSTATUS_GIT_BIN`
+    #[cfg(unix)]
+    pub fn script(&self, name: &str, body: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = self.path.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    /// Runs gitstatus in the repository
+    pub fn gitstatus(&self, args: &[&str]) -> Output {
+        self.gitstatus_with(args, &[])
+    }
+
+    /// Runs gitstatus in the repository with extra environment variables
+    pub fn gitstatus_with(&self, args: &[&str], vars: &[(&str, &str)]) -> Output {
+        gitstatus(&self.path, args, vars)
+    }
+
+    /// The stdout of a successful run
+    pub fn stdout(&self, args: &[&str]) -> String {
+        let output = self.gitstatus(args);
+        assert!(
+            output.status.success(),
+            "gitstatus {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Each field of the default space separated line by name, after checking that splitting
+    /// it on whitespace gives exactly one value per `--field-names` entry
+    pub fn fields(&self, args: &[&str]) -> HashMap<String, String> {
+        let names = self.stdout(&[args, &["--field-names"]].concat());
+        let values = self.stdout(args);
+        let names = names.split_whitespace().collect::<Vec<_>>();
+        let values = values.split_whitespace().collect::<Vec<_>>();
+        assert_eq!(names.len(), values.len(), "{:?} for {:?}", values, names);
+        names
+            .into_iter()
+            .zip(values)
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+}
+
+impl Drop for Repo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Runs the gitstatus binary from `dir`
+pub fn gitstatus(dir: &Path, args: &[&str], vars: &[(&str, &str)]) -> Output {
+    let mut command = git_env(Command::new(env!("CARGO_BIN_EXE_gitstatus")));
+    for (name, _) in env::vars().filter(|(name, _)| name.starts_with("GITSTATUS_")) {
+        command.env_remove(name);
+    }
+    command
+        .args(args)
+        .envs(vars.iter().copied())
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+/// Runs the gitstatus binary from `dir` with `input` on its stdin, for `--stdin`
+pub fn gitstatus_stdin(dir: &Path, args: &[&str], input: &[u8]) -> Output {
+    let mut command = git_env(Command::new(env!("CARGO_BIN_EXE_gitstatus")));
+    for (name, _) in env::vars().filter(|(name, _)| name.starts_with("GITSTATUS_")) {
+        command.env_remove(name);
+    }
+    let mut child = command
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Isolates a command from the user's and the system's git config, with an identity to commit
+fn git_env(mut command: Command) -> Command {
+    command
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("XDG_CONFIG_HOME", "/nonexistent")
+        .env("GIT_AUTHOR_NAME", "test")
+        .env("GIT_AUTHOR_EMAIL", "test@example.com")
+        .env("GIT_COMMITTER_NAME", "test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com");
+    command
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use common::Repo;
+
+const BREAKDOWN: &str = "{both_modified} {both_added} {both_deleted} {added_by_us} \
+    {added_by_them} {deleted_by_us} {deleted_by_them} {conflicts}";
+
+/// A merge stopped on one conflict of each of the seven unmerged kinds
+fn every_conflict() -> Repo {
+    let repo = Repo::new();
+    // distinct contents, so renames aren't paired with the wrong file
+    for name in ["modified", "renamed", "deleted-by-them", "deleted-by-us"] {
+        let lines = (1..=20)
+            .map(|n| format!("{} {}\n", name, n))
+            .collect::<String>();
+        repo.write(name, &lines);
+    }
+    repo.commit("base");
+
+    repo.git(&["checkout", "-q", "-b", "other"]);
+    repo.write("modified", "theirs\n");
+    repo.git(&["mv", "renamed", "renamed-by-them"]);
+    repo.remove("deleted-by-them");
+    repo.write("deleted-by-us", "theirs\n");
+    repo.write("added", "theirs\n");
+    repo.commit("theirs");
+
+    repo.git(&["checkout", "-q", "main"]);
+    repo.write("modified", "ours\n");
+    // renamed on both sides: DD renamed, AU renamed-by-us, UA renamed-by-them
+    repo.git(&["mv", "renamed", "renamed-by-us"]);
+    repo.write("deleted-by-them", "ours\n");
+    repo.remove("deleted-by-us");
+    repo.write("added", "ours\n");
+    repo.commit("ours");
+    repo.git_failing(&["merge", "-q", "other"]);
+    repo
+}
+
+#[test]
+fn every_unmerged_kind() {
+    let repo = every_conflict();
+    assert_eq!(
+        repo.stdout(&["--conflict-breakdown", "--format", BREAKDOWN]),
+        "1 1 1 1 1 1 1 7"
+    );
+}
+
+#[cfg(feature = "libgit2")]
+#[test]
+fn every_unmerged_kind_with_libgit2() {
+    let repo = every_conflict();
+    assert_eq!(
+        repo.stdout(&[
+            "--backend",
+            "libgit2",
+            "--conflict-breakdown",
+            "--format",
+            BREAKDOWN
+        ]),
+        "1 1 1 1 1 1 1 7"
+    );
+}
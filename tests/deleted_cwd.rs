@@ -0,0 +1,21 @@
+//! On its own, as the working directory is shared by the whole test process
+
+mod common;
+
+use common::Repo;
+use gitstatus::{gather, Error, Options};
+use std::{env, fs};
+
+#[test]
+fn gather_in_a_deleted_directory() {
+    let repo = Repo::with_commit();
+    let gone = repo.path.join("gone");
+    fs::create_dir(&gone).unwrap();
+    env::set_current_dir(&gone).unwrap();
+    fs::remove_dir(&gone).unwrap();
+
+    let options = Options::from_env_and_args(Vec::new(), Vec::new()).unwrap();
+    let status = gather(&options);
+    env::set_current_dir(env::temp_dir()).unwrap();
+    assert!(matches!(status, Err(Error::NotARepo)), "{:?}", status.err());
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use common::Repo;
+use std::process::{Command, Stdio};
+
+#[test]
+fn outside_a_repository_renders_nothing() {
+    let dir = Repo::dir();
+    let output = dir.gitstatus(&[]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn git_that_cant_be_spawned_renders_nothing() {
+    let repo = Repo::with_commit();
+    let output = repo.gitstatus_with(&[], &[("GITSTATUS_GIT_BIN", "/nonexistent/git")]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn missing_directory_renders_nothing() {
+    let dir = Repo::dir();
+    let missing = dir.path.join("missing");
+    let output = dir.gitstatus(&["-C", missing.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn git_failing_exits_with_2() {
+    let repo = Repo::with_commit();
+    let git = repo.script("git", "echo 'fatal: index file corrupt' >&2; exit 128");
+    let output = repo.gitstatus_with(&[], &[("GITSTATUS_GIT_BIN", git.to_str().unwrap())]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "gitstatus: index file corrupt\n"
+    );
+}
+
+#[test]
+fn no_git_on_path_renders_nothing() {
+    let repo = Repo::with_commit();
+    let empty = Repo::dir();
+    let output = repo.gitstatus_with(&[], &[("PATH", empty.path.to_str().unwrap())]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn closed_stdout_exits_cleanly() {
+    let repo = Repo::with_commit();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_gitstatus"));
+    for (name, _) in std::env::vars().filter(|(name, _)| name.starts_with("GITSTATUS_")) {
+        command.env_remove(name);
+    }
+    let mut child = command
+        .arg("--json")
+        .current_dir(&repo.path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // like `| head -c0`: the reader is gone before anything is written
+    drop(child.stdout.take());
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        output.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// The `--quiet` exit code and the rendered `clean` field
+fn quiet_and_clean(repo: &Repo, vars: &[(&str, &str)]) -> (Option<i32>, String) {
+    let quiet = repo.gitstatus_with(&["--quiet"], vars).status.code();
+    let clean = repo.gitstatus_with(&["--fields", "clean"], vars).stdout;
+    (quiet, String::from_utf8(clean).unwrap())
+}
+
+#[test]
+fn bare_repository_is_clean() {
+    let repo = Repo::dir();
+    repo.git(&["init", "-q", "--bare"]);
+    assert_eq!(quiet_and_clean(&repo, &[]), (Some(0), "1".to_string()));
+    let json = repo.stdout(&["--json"]);
+    assert!(json.contains(r#""clean":true"#), "{}", json);
+}
+
+#[cfg(unix)]
+#[test]
+fn timed_out_repository_isnt_clean() {
+    let repo = Repo::with_commit();
+    let git = repo.script(
+        "stub-git",
+        r#"case " $* " in
+    *" status "*) sleep 2 ;;
+    *) exec git "$@" ;;
+esac"#,
+    );
+    let vars = [
+        ("GITSTATUS_GIT_BIN", git.to_str().unwrap()),
+        ("GITSTATUS_TIMEOUT_MS", "100"),
+    ];
+    assert_eq!(quiet_and_clean(&repo, &vars), (Some(1), "0".to_string()));
+}
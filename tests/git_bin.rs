@@ -0,0 +1,84 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Repo;
+use std::fs;
+
+#[test]
+fn runs_the_git_it_is_pointed_at() {
+    let repo = Repo::with_commit();
+    let log = repo.path.join("calls");
+    let git = repo.script(
+        "stub-git",
+        &format!(
+            r#"echo "$*" >> '{}'
+case " $* " in
+    *" status "*) printf '## stub...origin/stub [ahead 4]\0?? new\0' ;;
+    *) exec git "$@" ;;
+esac"#,
+            log.display()
+        ),
+    );
+    let output = repo.gitstatus_with(
+        &[
+            "--porcelain-version",
+            "1",
+            "--format",
+            "{branch} {ahead} {untracked}",
+        ],
+        &[("GITSTATUS_GIT_BIN", git.to_str().unwrap())],
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "stub 4 1");
+    let calls = fs::read_to_string(log).unwrap();
+    assert!(
+        calls
+            .lines()
+            .any(|call| call.starts_with("status --porcelain=v1")),
+        "{}",
+        calls
+    );
+}
+
+/// The git calls of a run, through a stub logging them before running git
+fn git_calls(repo: &Repo, args: &[&str]) -> Vec<String> {
+    let log = repo.path.join("calls");
+    let _ = fs::remove_file(&log);
+    let git = repo.script(
+        "stub-git",
+        &format!(r#"echo "$*" >> '{}'; exec git "$@""#, log.display()),
+    );
+    let output = repo.gitstatus_with(args, &[("GITSTATUS_GIT_BIN", git.to_str().unwrap())]);
+    assert!(output.status.success());
+    fs::read_to_string(log)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn no_branch_skips_naming_a_detached_head() {
+    let repo = Repo::with_commit();
+    repo.git(&["tag", "v1.0"]);
+    repo.git(&["checkout", "-q", "--detach"]);
+    let naming = |call: &String| {
+        [
+            "symbolic-ref",
+            "for-each-ref",
+            "describe",
+            "rev-parse --short",
+        ]
+        .iter()
+        .any(|command| call.starts_with(command))
+    };
+
+    let named = git_calls(&repo, &[]);
+    assert!(named.iter().any(naming), "{:?}", named);
+    let calls = git_calls(&repo, &["--no-branch"]);
+    assert!(!calls.iter().any(naming), "{:?}", calls);
+    assert_eq!(
+        calls.len(),
+        named.iter().filter(|call| !naming(call)).count()
+    );
+}
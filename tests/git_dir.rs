@@ -0,0 +1,29 @@
+mod common;
+
+use common::{gitstatus, Repo};
+use std::fs;
+
+#[test]
+fn git_dir_and_work_tree_from_the_environment() {
+    let repo = Repo::with_commit();
+    repo.write("README", "stashed\n");
+    repo.git(&["stash", "-q"]);
+    repo.write("README", "changed\n");
+    repo.write("new", "new\n");
+
+    // the git dir out of the work tree, found only through the variables
+    let git_dir = Repo::dir();
+    let moved = git_dir.path.join("repo.git");
+    fs::rename(repo.path.join(".git"), &moved).unwrap();
+    let elsewhere = Repo::dir();
+    let output = gitstatus(
+        &elsewhere.path,
+        &["--format", "{branch} {changed} {untracked} {stashed}"],
+        &[
+            ("GIT_DIR", moved.to_str().unwrap()),
+            ("GIT_WORK_TREE", repo.path.to_str().unwrap()),
+        ],
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "main 1 1 1");
+}
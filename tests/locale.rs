@@ -0,0 +1,44 @@
+mod common;
+
+use common::Repo;
+
+/// A locale git has translations for: `Auf Branch main` in `git status`
+const GERMAN: [(&str, &str); 4] = [
+    ("LANGUAGE", "de"),
+    ("LANG", "de_DE.UTF-8"),
+    ("LC_ALL", "C.UTF-8"),
+    ("LC_MESSAGES", "de_DE.UTF-8"),
+];
+
+fn format(repo: &Repo, args: &[&str]) -> String {
+    let output = repo.gitstatus_with(args, &GERMAN);
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn branch_under_a_non_english_locale() {
+    let repo = Repo::with_commit();
+    assert_eq!(format(&repo, &["--format", "{branch}"]), "main");
+}
+
+#[test]
+fn unborn_branch_under_a_non_english_locale() {
+    let repo = Repo::new();
+    repo.write("new", "new\n");
+    repo.git(&["add", "new"]);
+    for porcelain in ["1", "2"] {
+        assert_eq!(
+            format(
+                &repo,
+                &[
+                    "--porcelain-version",
+                    porcelain,
+                    "--format",
+                    "{branch} {staged}"
+                ]
+            ),
+            "main 1"
+        );
+    }
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::Repo;
+
+#[test]
+fn no_operation_keeps_the_fields_in_place() {
+    let repo = Repo::with_commit();
+    let fields = repo.fields(&[]);
+    assert_eq!(fields["operation"], "-");
+    assert_eq!(fields["step"], "-");
+    assert_eq!(fields["clean"], "1");
+}
+
+#[test]
+fn merge_in_progress() {
+    let repo = Repo::with_commit();
+    repo.git(&["checkout", "-q", "-b", "other"]);
+    repo.write("README", "other\n");
+    repo.commit("other");
+    repo.git(&["checkout", "-q", "main"]);
+    repo.write("README", "main\n");
+    repo.commit("main");
+    repo.git_failing(&["merge", "-q", "other"]);
+
+    let fields = repo.fields(&[]);
+    assert_eq!(fields["operation"], "merge");
+    assert_eq!(fields["step"], "-");
+    assert_eq!(fields["conflicts"], "1");
+}
+
+#[test]
+fn null_separated_empty_fields_stay_empty() {
+    let repo = Repo::with_commit();
+    let out = repo.stdout(&["-z", "--fields", "branch,operation,staged"]);
+    assert_eq!(out.split('\0').collect::<Vec<_>>(), ["main", "", "0"]);
+}
+
+#[test]
+fn command_line_output_flag_replaces_the_environment_one() {
+    let repo = Repo::with_commit();
+    let output = repo.gitstatus_with(&["--short"], &[("GITSTATUS_JSON", "1")]);
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).starts_with('{'));
+    assert_eq!(repo.stdout(&["--short"]).as_bytes(), output.stdout);
+}
+
+#[test]
+fn rebase_stopped_on_a_conflict() {
+    let repo = Repo::with_commit();
+    repo.git(&["checkout", "-q", "-b", "feature"]);
+    repo.write("README", "feature\n");
+    repo.commit("conflicting");
+    repo.write("second", "second\n");
+    repo.commit("second");
+    repo.write("third", "third\n");
+    repo.commit("third");
+    repo.git(&["checkout", "-q", "main"]);
+    repo.write("README", "main\n");
+    repo.commit("main");
+    repo.git(&["checkout", "-q", "feature"]);
+    repo.git_failing(&["rebase", "-q", "main"]);
+
+    assert_eq!(
+        repo.stdout(&["--with-operation", "--format", "{operation_state}"]),
+        "rebase 1/3 conflicts=1"
+    );
+}
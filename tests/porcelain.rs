@@ -0,0 +1,79 @@
+mod common;
+
+use common::Repo;
+
+const COUNTS: &str = "{staged} {changed} {untracked} {deleted} {renamed} {clean}";
+
+#[test]
+fn forced_v1_on_a_modern_git() {
+    let repo = Repo::with_commit();
+    repo.write("gone", "gone\n");
+    repo.write("old", "old name\n");
+    repo.commit("more");
+    repo.write("README", "changed\n");
+    repo.write("staged", "staged\n");
+    repo.git(&["add", "staged"]);
+    repo.git(&["mv", "old", "new name"]);
+    repo.remove("gone");
+    repo.write("untracked", "untracked\n");
+
+    let v1 = repo.stdout(&["--porcelain-version", "1", "--format", COUNTS]);
+    assert_eq!(v1, "2 1 1 1 1 0");
+    assert_eq!(
+        v1,
+        repo.stdout(&["--porcelain-version", "2", "--format", COUNTS])
+    );
+    assert_eq!(
+        repo.fields(&["--porcelain-version", "1"]),
+        repo.fields(&["--porcelain-version", "2"])
+    );
+}
+
+/// How git versions phrase the `##` header of a detached HEAD
+#[cfg(unix)]
+const DETACHED_HEADERS: [&str; 4] = [
+    "## HEAD (no branch)",
+    "## HEAD (detached at 1234567)",
+    "## (HEAD detached at 1234567)",
+    "## HEAD",
+];
+
+/// A git answering `status` with the `$HEADER` header alone
+#[cfg(unix)]
+fn header_stub(dir: &Repo) -> String {
+    let git = dir.script(
+        "git",
+        r#"case " $* " in
+    *" status "*) printf '%s\0' "$HEADER" ;;
+    *) exec git "$@" ;;
+esac"#,
+    );
+    git.to_str().unwrap().to_string()
+}
+
+#[cfg(unix)]
+#[test]
+fn detached_whatever_the_header_says() {
+    let repo = Repo::with_commit();
+    let hash = repo.git(&["rev-parse", "--short", "HEAD"]);
+    repo.git(&["checkout", "-q", "--detach"]);
+    let stub = Repo::dir();
+    let git = header_stub(&stub);
+    for header in DETACHED_HEADERS {
+        let output = repo.gitstatus_with(
+            &[
+                "--porcelain-version",
+                "1",
+                "--format",
+                "{branch} {detached}",
+            ],
+            &[("GITSTATUS_GIT_BIN", &git), ("HEADER", header)],
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            format!("{} 1", hash.trim()),
+            "{}",
+            header
+        );
+    }
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::Repo;
+
+#[test]
+fn null_separated_records_around_an_unreadable_path() {
+    let first = Repo::with_commit();
+    let second = Repo::with_commit();
+    second.write("new", "new\n");
+    let missing = first.path.join("missing");
+    let repos = [&first.path, &missing, &second.path]
+        .map(|path| path.to_str().unwrap().to_string())
+        .join(",");
+    let dir = Repo::dir();
+    let output = dir.gitstatus(&[
+        "--repos",
+        &repos,
+        "-z",
+        "--fields",
+        "branch,operation,untracked",
+    ]);
+    assert!(output.status.success());
+
+    let out = String::from_utf8(output.stdout).unwrap();
+    let records = out
+        .strip_suffix('\n')
+        .unwrap()
+        .split('\n')
+        .map(|record| record.split('\0').collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        records,
+        [
+            vec![first.path.to_str().unwrap(), "main", "", "0"],
+            vec![missing.to_str().unwrap(), ""],
+            vec![second.path.to_str().unwrap(), "main", "", "1"],
+        ]
+    );
+}
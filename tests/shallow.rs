@@ -0,0 +1,27 @@
+mod common;
+
+use common::Repo;
+
+#[test]
+fn shallow_clone() {
+    let origin = Repo::with_commit();
+    origin.write("second", "second\n");
+    origin.commit("second");
+    let repo = Repo::clone_of(&origin, &["--depth", "1"]);
+    assert_eq!(
+        repo.stdout(&["--with-shallow", "--format", "{shallow}"]),
+        "1"
+    );
+
+    let output = repo.gitstatus(&["--with-shallow", "--verbose", "--format", "{shallow}"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("shallow clone"));
+}
+
+#[test]
+fn full_clone_isnt_shallow() {
+    let origin = Repo::with_commit();
+    let repo = Repo::clone_of(&origin, &[]);
+    let output = repo.gitstatus(&["--with-shallow", "--verbose", "--format", "{shallow}"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "0");
+    assert!(output.stderr.is_empty());
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::Repo;
+
+fn stash(repo: &Repo, message: Option<&str>) {
+    repo.write("README", "stashed\n");
+    match message {
+        Some(message) => repo.git(&["stash", "push", "-q", "-m", message]),
+        None => repo.git(&["stash", "-q"]),
+    };
+}
+
+#[test]
+fn stashes_on_two_branches() {
+    let repo = Repo::with_commit();
+    stash(&repo, None);
+    stash(&repo, Some("named"));
+    repo.git(&["checkout", "-q", "-b", "feature"]);
+    stash(&repo, None);
+    // a branch named like the other one with a suffix has stashes of its own
+    repo.git(&["checkout", "-q", "-b", "feature-2"]);
+    stash(&repo, None);
+
+    let stashed = |args: &[&str]| repo.stdout(&[args, &["--format", "{stashed}"]].concat());
+    assert_eq!(stashed(&[]), "4");
+    assert_eq!(stashed(&["--stash-current-branch"]), "1");
+    repo.git(&["checkout", "-q", "feature"]);
+    assert_eq!(stashed(&["--stash-current-branch"]), "1");
+    repo.git(&["checkout", "-q", "main"]);
+    assert_eq!(stashed(&["--stash-current-branch"]), "2");
+}
+
+#[test]
+fn stashes_from_a_linked_worktree() {
+    let repo = Repo::with_commit();
+    stash(&repo, None);
+    stash(&repo, Some("second"));
+    let linked = Repo::dir();
+    repo.git(&[
+        "worktree",
+        "add",
+        "-q",
+        "-b",
+        "linked",
+        linked.path.to_str().unwrap(),
+    ]);
+
+    // the stash ref is shared by every worktree, while `.git` is a file in the linked one
+    assert!(linked.path.join(".git").is_file());
+    assert_eq!(
+        linked.stdout(&["--format", "{branch} {stashed}"]),
+        "linked 2"
+    );
+    assert_eq!(
+        linked.stdout(&["--stash-current-branch", "--format", "{stashed}"]),
+        "0"
+    );
+}
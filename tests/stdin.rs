@@ -0,0 +1,47 @@
+mod common;
+
+use common::{gitstatus_stdin, Repo};
+
+#[test]
+fn net_divergence_of_both_sides() {
+    let dir = Repo::dir();
+    let output = gitstatus_stdin(
+        &dir.path,
+        &["--stdin", "--net-divergence", "--format", "{divergence}"],
+        b"## main...origin/main [ahead 3, behind 1]\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2");
+}
+
+#[test]
+fn net_divergence_when_behind() {
+    let dir = Repo::dir();
+    let output = gitstatus_stdin(
+        &dir.path,
+        &["--stdin", "--net-divergence", "--format", "{divergence}"],
+        b"## main...origin/main [ahead 1, behind 4]\n",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "-3");
+}
+
+#[test]
+fn porcelain_v2_without_null_separators() {
+    let dir = Repo::dir();
+    let input = b"# branch.oid 1234567\n# branch.head main\n\
+        1 .M N... 100644 100644 100644 1111111 1111111 changed\n? new\n";
+    for porcelain in ["auto", "2"] {
+        let output = gitstatus_stdin(
+            &dir.path,
+            &[
+                "--stdin",
+                "--porcelain-version",
+                porcelain,
+                "--format",
+                "{branch} {changed} {untracked}",
+            ],
+            input,
+        );
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "main 1 1");
+    }
+}
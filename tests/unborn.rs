@@ -0,0 +1,50 @@
+mod common;
+
+use common::Repo;
+
+/// A clone with an unborn `fresh` branch tracking `origin/main`, which is a commit ahead
+fn unborn_with_upstream() -> (Repo, Repo) {
+    let origin = Repo::with_commit();
+    let repo = Repo::clone_of(&origin, &[]);
+    repo.git(&["checkout", "-q", "--orphan", "fresh"]);
+    repo.git(&["rm", "-q", "-r", "--cached", "."]);
+    repo.git(&["config", "branch.fresh.remote", "origin"]);
+    repo.git(&["config", "branch.fresh.merge", "refs/heads/main"]);
+    (origin, repo)
+}
+
+#[test]
+fn unborn_branch_with_an_upstream() {
+    let (_origin, repo) = unborn_with_upstream();
+    let fields = repo.fields(&[]);
+    assert_eq!(fields["branch"], "fresh");
+    assert_eq!(fields["upstream"], "origin/main");
+    assert_eq!((&*fields["ahead"], &*fields["behind"]), ("0", "0"));
+}
+
+/// Whatever the wording of the header, a branch without commits is neither ahead nor behind
+#[cfg(unix)]
+#[test]
+fn unborn_branch_with_a_plain_header() {
+    let (_origin, repo) = unborn_with_upstream();
+    let git = repo.script(
+        "stub-git",
+        r#"case " $* " in
+    *" status "*) git "$@" | sed 's/No commits yet on //; s/origin\/main/origin\/main [ahead 2, behind 1]/' ;;
+    *) exec git "$@" ;;
+esac"#,
+    );
+    let git = git.to_str().unwrap();
+    for porcelain in ["1", "2"] {
+        let output = repo.gitstatus_with(
+            &[
+                "--porcelain-version",
+                porcelain,
+                "--format",
+                "{branch} {ahead} {behind}",
+            ],
+            &[("GITSTATUS_GIT_BIN", git)],
+        );
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "fresh 0 0");
+    }
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use common::Repo;
+
+/// A committed repository with `top` and a nested `new/` directory left untracked
+fn repo_with_untracked() -> Repo {
+    let repo = Repo::with_commit();
+    repo.write("top", "top\n");
+    repo.write("new/a", "a\n");
+    repo.write("new/sub/b", "b\n");
+    repo
+}
+
+fn untracked_and_clean(repo: &Repo, mode: &str) -> String {
+    repo.stdout(&["--untracked-mode", mode, "--format", "{untracked} {clean}"])
+}
+
+#[test]
+fn normal_mode_groups_a_new_directory() {
+    assert_eq!(untracked_and_clean(&repo_with_untracked(), "normal"), "2 0");
+}
+
+#[test]
+fn all_mode_lists_every_file() {
+    assert_eq!(untracked_and_clean(&repo_with_untracked(), "all"), "3 0");
+}
+
+#[test]
+fn no_mode_leaves_untracked_files_out() {
+    assert_eq!(untracked_and_clean(&repo_with_untracked(), "no"), "0 1");
+}
+
+#[test]
+fn modes_agree_on_changes() {
+    let repo = repo_with_untracked();
+    repo.write("README", "changed\n");
+    for mode in ["normal", "all", "no"] {
+        assert_eq!(
+            repo.stdout(&["--untracked-mode", mode, "--format", "{changed} {clean}"]),
+            "1 0"
+        );
+    }
+}
+
+#[test]
+fn default_mode_follows_the_config() {
+    let repo = repo_with_untracked();
+    for (config, expected) in [("no", "0 1"), ("all", "3 0"), ("normal", "2 0")] {
+        repo.git(&["config", "status.showUntrackedFiles", config]);
+        assert_eq!(repo.stdout(&["--format", "{untracked} {clean}"]), expected);
+    }
+}
+
+#[cfg(feature = "libgit2")]
+#[test]
+fn default_mode_follows_the_config_with_libgit2() {
+    let repo = repo_with_untracked();
+    for (config, expected) in [("no", "0 1"), ("all", "3 0"), ("normal", "2 0")] {
+        repo.git(&["config", "status.showUntrackedFiles", config]);
+        assert_eq!(
+            repo.stdout(&["--backend", "libgit2", "--format", "{untracked} {clean}"]),
+            expected,
+            "{}",
+            config
+        );
+    }
+    // the flag still wins over the config
+    assert_eq!(
+        repo.stdout(&[
+            "--backend",
+            "libgit2",
+            "--untracked-mode",
+            "all",
+            "--format",
+            "{untracked}"
+        ]),
+        "3"
+    );
+}